@@ -0,0 +1,218 @@
+use crate::transport::BoxFuture;
+use crate::Error;
+use futures_util::stream::FuturesUnordered;
+use futures_util::Stream;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
+/// The default max number of requests in flight for a batch
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// The default max number of requests in flight to one host for a batch
+const DEFAULT_MAX_PER_HOST: usize = 2;
+
+/// A function that fetches an item
+type FetchFn<K, T> = Arc<dyn Fn(K) -> BoxFuture<'static, Result<T, Error>> + Send + Sync>;
+
+/// A request in flight, resolving to its host, key and result
+type InFlight<K, T> = BoxFuture<'static, (String, K, Result<T, Error>)>;
+
+/// A stream of results from fetching many items, like game pages.
+///
+/// Items are yielded as they complete, not in the order they were given.
+/// Hosts take turns, so that a long run of urls on one host does not starve the others.
+///
+/// Dropping this, or calling [`Batch::cancel`], cancels all requests in flight.
+/// Requests are also bounded by the client-wide limit set with
+/// [`Client::set_max_concurrent_requests`](crate::Client::set_max_concurrent_requests).
+pub struct Batch<K, T> {
+    /// The function that fetches an item
+    fetch: FetchFn<K, T>,
+
+    /// Queued items by host, in the order hosts take turns
+    queues: VecDeque<(String, VecDeque<K>)>,
+
+    /// Requests in flight
+    in_flight: FuturesUnordered<InFlight<K, T>>,
+
+    /// The number of requests in flight for each host
+    in_flight_per_host: HashMap<String, usize>,
+
+    /// The max number of requests in flight
+    concurrency: usize,
+
+    /// The max number of requests in flight to one host
+    max_per_host: usize,
+}
+
+impl<K, T> Batch<K, T>
+where
+    K: Clone + Send + 'static,
+    T: Send + 'static,
+{
+    /// Make a new batch.
+    pub(crate) fn new<I, H>(items: I, host: H, fetch: FetchFn<K, T>) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        H: Fn(&K) -> String,
+    {
+        let mut queues: VecDeque<(String, VecDeque<K>)> = VecDeque::new();
+        let mut indexes = HashMap::new();
+        for item in items {
+            let host = host(&item);
+            let index = *indexes.entry(host.clone()).or_insert_with(|| {
+                queues.push_back((host, VecDeque::new()));
+                queues.len() - 1
+            });
+            queues[index].1.push_back(item);
+        }
+
+        Self {
+            fetch,
+            queues,
+            in_flight: FuturesUnordered::new(),
+            in_flight_per_host: HashMap::new(),
+            concurrency: DEFAULT_CONCURRENCY,
+            max_per_host: DEFAULT_MAX_PER_HOST,
+        }
+    }
+
+    /// Set the max number of requests in flight.
+    ///
+    /// This defaults to 8.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set the max number of requests in flight to one host.
+    ///
+    /// This defaults to 2.
+    pub fn with_max_per_host(mut self, max_per_host: usize) -> Self {
+        self.max_per_host = max_per_host.max(1);
+        self
+    }
+
+    /// Get the number of items that have not completed yet.
+    pub fn remaining(&self) -> usize {
+        self.in_flight.len()
+            + self
+                .queues
+                .iter()
+                .map(|(_, queue)| queue.len())
+                .sum::<usize>()
+    }
+
+    /// Cancel all requests in flight and drop all queued items.
+    ///
+    /// The stream ends after this.
+    pub fn cancel(&mut self) {
+        self.queues.clear();
+        self.in_flight = FuturesUnordered::new();
+        self.in_flight_per_host.clear();
+    }
+
+    /// Start requests until the limits are hit.
+    fn fill(&mut self) {
+        while self.in_flight.len() < self.concurrency {
+            let Some((host, item)) = self.next_item() else {
+                break;
+            };
+
+            *self.in_flight_per_host.entry(host.clone()).or_default() += 1;
+            let future = (self.fetch)(item.clone());
+            self.in_flight
+                .push(Box::pin(async move { (host, item, future.await) }));
+        }
+    }
+
+    /// Take the next item from the next host with room for another request.
+    fn next_item(&mut self) -> Option<(String, K)> {
+        for _ in 0..self.queues.len() {
+            let (host, mut queue) = self.queues.pop_front()?;
+            let in_flight = self.in_flight_per_host.get(&host).copied().unwrap_or(0);
+            if in_flight >= self.max_per_host {
+                self.queues.push_back((host, queue));
+                continue;
+            }
+
+            let item = queue.pop_front()?;
+            if !queue.is_empty() {
+                self.queues.push_back((host.clone(), queue));
+            }
+            return Some((host, item));
+        }
+
+        None
+    }
+}
+
+impl<K, T> Stream for Batch<K, T>
+where
+    K: Clone + Send + Unpin + 'static,
+    T: Send + 'static,
+{
+    type Item = (K, Result<T, Error>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.fill();
+
+        match Pin::new(&mut this.in_flight).poll_next(cx) {
+            Poll::Ready(Some((host, item, result))) => {
+                if let Some(in_flight) = this.in_flight_per_host.get_mut(&host) {
+                    *in_flight -= 1;
+                }
+                Poll::Ready(Some((item, result)))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<K, T> std::fmt::Debug for Batch<K, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Batch")
+            .field("in_flight", &self.in_flight.len())
+            .field("in_flight_per_host", &self.in_flight_per_host)
+            .field("concurrency", &self.concurrency)
+            .field("max_per_host", &self.max_per_host)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[tokio::test]
+    async fn fairness() {
+        let items = ["a/1", "a/2", "a/3", "a/4", "b/1", "c/1"];
+        let fetch: FetchFn<&'static str, ()> = Arc::new(|_| Box::pin(async { Ok(()) }));
+        let batch = Batch::new(
+            items,
+            |item| {
+                item.split('/')
+                    .next()
+                    .expect("failed to split item")
+                    .to_string()
+            },
+            fetch,
+        )
+        .with_concurrency(1);
+
+        let order: Vec<_> = batch.map(|(item, _)| item).collect().await;
+        assert_eq!(order, ["a/1", "b/1", "c/1", "a/2", "a/3", "a/4"]);
+    }
+}
@@ -0,0 +1,558 @@
+//! A blocking client.
+//!
+//! This wraps the async [`Client`](crate::Client) with its own runtime.
+//! Its methods must not be called from inside an async runtime, as they will panic.
+
+use crate::download::CancelHandle;
+use crate::download::ProgressFn;
+use crate::download::RemoteFile;
+use crate::download::RemoteFileFn;
+use crate::download::Verification;
+use crate::drift::DriftReport;
+use crate::drift::PageKind;
+use crate::executor::Executor;
+use crate::metrics::Metrics;
+use crate::queue::QueueError;
+use crate::queue::QueueItem;
+use crate::transport::Transport;
+use crate::types::download_page;
+use crate::types::game_page;
+use crate::DownloadInfo;
+use crate::DownloadPage;
+use crate::DownloadPageUrlInfo;
+use crate::Error;
+use crate::GamePage;
+use crate::GameUrl;
+use crate::Parsed;
+use crate::PartialGamePage;
+use crate::PurchaseDialog;
+use crate::Selectors;
+use crate::Upload;
+use crate::UploadId;
+use crate::UploadInfo;
+use futures_util::StreamExt;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+/// A blocking iterator over the results of fetching many items.
+///
+/// See the async [`Batch`](crate::Batch).
+#[derive(Debug)]
+pub struct Batch<K, T> {
+    /// The async batch
+    batch: crate::Batch<K, T>,
+
+    /// The runtime the batch is driven on
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl<K, T> Batch<K, T>
+where
+    K: Clone + Send + 'static,
+    T: Send + 'static,
+{
+    /// Set the max number of requests in flight.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.batch = self.batch.with_concurrency(concurrency);
+        self
+    }
+
+    /// Set the max number of requests in flight to one host.
+    pub fn with_max_per_host(mut self, max_per_host: usize) -> Self {
+        self.batch = self.batch.with_max_per_host(max_per_host);
+        self
+    }
+
+    /// Get the number of items that have not completed yet.
+    pub fn remaining(&self) -> usize {
+        self.batch.remaining()
+    }
+
+    /// Cancel all requests in flight and drop all queued items.
+    pub fn cancel(&mut self) {
+        self.batch.cancel()
+    }
+}
+
+impl<K, T> Iterator for Batch<K, T>
+where
+    K: Clone + Send + Unpin + 'static,
+    T: Send + 'static,
+{
+    type Item = (K, Result<T, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.batch.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        futures_util::Stream::size_hint(&self.batch)
+    }
+}
+
+/// A blocking file download.
+///
+/// See the async [`Download`](crate::download::Download).
+#[derive(Debug)]
+pub struct Download {
+    /// The async download
+    download: crate::download::Download,
+
+    /// The runtime the download is driven on
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl Download {
+    /// Set the max number of chunks in flight.
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.download = self.download.with_connections(connections);
+        self
+    }
+
+    /// Set the size of a chunk, in bytes.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.download = self.download.with_chunk_size(chunk_size);
+        self
+    }
+
+    /// Resume a partial download, keeping the first `offset` bytes of the file.
+    pub fn resume_from(mut self, offset: u64) -> Self {
+        self.download = self.download.resume_from(offset);
+        self
+    }
+
+    /// Only resume if the file on the server still matches what was reported before.
+    pub fn if_unchanged(mut self, expected: RemoteFile) -> Self {
+        self.download = self.download.if_unchanged(expected);
+        self
+    }
+
+    /// Set a function to call with the download progress.
+    pub fn with_progress(mut self, progress: ProgressFn) -> Self {
+        self.download = self.download.with_progress(progress);
+        self
+    }
+
+    /// Set a function to call with what the server reports about the file.
+    pub fn with_remote_file(mut self, on_remote_file: RemoteFileFn) -> Self {
+        self.download = self.download.with_remote_file(on_remote_file);
+        self
+    }
+
+    /// Get a handle to cancel this download.
+    ///
+    /// The handle can be used from another thread while [`Download::run`] blocks.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.download.cancel_handle()
+    }
+
+    /// Run the download, returning the size of the file.
+    pub fn run(self) -> Result<u64, Error> {
+        self.runtime.block_on(self.download.run())
+    }
+}
+
+/// A blocking persistent download queue.
+///
+/// See the async [`DownloadQueue`](crate::queue::DownloadQueue).
+#[derive(Debug)]
+pub struct DownloadQueue {
+    /// The async queue
+    queue: crate::queue::DownloadQueue,
+
+    /// The runtime the queue is driven on
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl DownloadQueue {
+    /// Open a queue file, or start an empty queue if it does not exist.
+    pub fn open<P>(client: &Client, path: P) -> Result<Self, QueueError>
+    where
+        P: Into<PathBuf>,
+    {
+        Ok(Self {
+            queue: crate::queue::DownloadQueue::open(client.client.clone(), path)?,
+            runtime: client.runtime.clone(),
+        })
+    }
+
+    /// Set the max number of attempts for an item.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.queue = self.queue.with_max_attempts(max_attempts);
+        self
+    }
+
+    /// Set the delay before the first retry, and the max delay between retries.
+    pub fn with_backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.queue = self.queue.with_backoff(base, max);
+        self
+    }
+
+    /// Set the max number of chunks in flight for a download.
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.queue = self.queue.with_connections(connections);
+        self
+    }
+
+    /// Get a copy of the pending items.
+    pub fn items(&self) -> Vec<QueueItem> {
+        self.queue.items()
+    }
+
+    /// Add an upload to download to the end of the queue, and save the queue.
+    pub fn push<P>(
+        &self,
+        game_url: GameUrl,
+        upload_id: UploadId,
+        destination: P,
+    ) -> Result<(), QueueError>
+    where
+        P: Into<PathBuf>,
+    {
+        self.queue.push(game_url, upload_id, destination)
+    }
+
+    /// Download items until the queue is empty.
+    pub fn run(&self) -> Result<Vec<(QueueItem, Error)>, QueueError> {
+        self.runtime.block_on(self.queue.run())
+    }
+}
+
+/// A blocking download of every upload of a game.
+///
+/// See the async [`GameDownload`](crate::game_download::GameDownload).
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct GameDownload {
+    /// The async game download
+    download: crate::game_download::GameDownload,
+
+    /// The runtime the download is driven on
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+#[cfg(feature = "serde")]
+impl GameDownload {
+    /// Only download uploads for this platform.
+    pub fn with_platform(mut self, platform: crate::Platform) -> Self {
+        self.download = self.download.with_platform(platform);
+        self
+    }
+
+    /// Only download uploads with this tag.
+    pub fn with_tag(mut self, tag: crate::UploadTag) -> Self {
+        self.download = self.download.with_tag(tag);
+        self
+    }
+
+    /// Skip uploads with this tag.
+    pub fn without_tag(mut self, tag: crate::UploadTag) -> Self {
+        self.download = self.download.without_tag(tag);
+        self
+    }
+
+    /// Set the max number of chunks in flight for a download.
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.download = self.download.with_connections(connections);
+        self
+    }
+
+    /// Download the selected uploads, then write the manifest.
+    pub fn run(self) -> Result<crate::game_download::Manifest, Error> {
+        self.runtime.block_on(self.download.run())
+    }
+}
+
+/// A blocking client.
+///
+/// See the async [`Client`](crate::Client) for details on each method.
+#[derive(Debug, Clone)]
+pub struct Client {
+    /// The async client
+    client: crate::Client,
+
+    /// The runtime the async client is driven on
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl Client {
+    /// Make a new client
+    pub fn new() -> Self {
+        Self::from_async(crate::Client::new())
+    }
+
+    /// Make a new client that sends requests through the given transport.
+    pub fn with_transport(transport: Arc<dyn Transport>) -> Self {
+        Self::from_async(crate::Client::with_transport(transport))
+    }
+
+    /// Make a new client from an async client.
+    ///
+    /// Clones of the async client share selectors with this client.
+    pub fn from_async(client: crate::Client) -> Self {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build tokio runtime");
+
+        Self {
+            client,
+            runtime: Arc::new(runtime),
+        }
+    }
+
+    /// Get the async client.
+    pub fn as_async(&self) -> &crate::Client {
+        &self.client
+    }
+
+    /// Get the transport requests are sent through.
+    pub fn transport(&self) -> &Arc<dyn Transport> {
+        self.client.transport()
+    }
+
+    /// Set the executor pages are parsed on.
+    pub fn set_executor(&mut self, executor: Arc<dyn Executor>) {
+        self.client.set_executor(executor)
+    }
+
+    /// Limit the rate of downloads, in bytes per second.
+    pub fn set_max_download_rate(&mut self, bytes_per_second: u64) {
+        self.client.set_max_download_rate(bytes_per_second)
+    }
+
+    /// Set the metrics hook.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        self.client.set_metrics(metrics)
+    }
+
+    /// Set the itch.io api key.
+    pub fn set_api_key(&mut self, api_key: &str) {
+        self.client.set_api_key(api_key)
+    }
+
+    /// Get the selectors used to parse pages.
+    pub fn selectors(&self) -> Arc<Selectors> {
+        self.client.selectors()
+    }
+
+    /// Limit the number of requests in flight.
+    pub fn set_max_concurrent_requests(&mut self, limit: usize) {
+        self.client.set_max_concurrent_requests(limit)
+    }
+
+    /// Replace the selectors used to parse pages.
+    pub fn set_selectors(&self, selectors: Selectors) {
+        self.client.set_selectors(selectors)
+    }
+
+    /// Get the latest csrf token for the session a game page is fetched with.
+    pub fn csrf_token(&self, game_url: &GameUrl) -> Option<String> {
+        self.client.csrf_token(game_url)
+    }
+
+    /// Set the csrf token for the session a game page is fetched with.
+    pub fn set_csrf_token(&self, game_url: &GameUrl, csrf_token: String) {
+        self.client.set_csrf_token(game_url, csrf_token)
+    }
+
+    /// Fetch a game page to get a fresh csrf token for its session.
+    pub fn refresh_csrf_token(&self, game_url: &GameUrl) -> Result<String, Error> {
+        self.block_on(self.client.refresh_csrf_token(game_url))
+    }
+
+    /// Drive a future to completion.
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        self.runtime.block_on(future)
+    }
+
+    /// Get a game page.
+    pub fn get_game_page(&self, url: &GameUrl) -> Result<GamePage, Error> {
+        self.block_on(self.client.get_game_page(url))
+    }
+
+    /// Get a game page, parsing it in lenient mode.
+    pub fn get_game_page_lenient(
+        &self,
+        url: &GameUrl,
+    ) -> Result<Parsed<PartialGamePage, game_page::FromHtmlError>, Error> {
+        self.block_on(self.client.get_game_page_lenient(url))
+    }
+
+    /// Get many game pages.
+    pub fn get_game_pages<I>(&self, urls: I) -> Batch<GameUrl, GamePage>
+    where
+        I: IntoIterator<Item = GameUrl>,
+    {
+        Batch {
+            batch: self.client.get_game_pages(urls),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Get the download info for a given game upload by id.
+    pub fn get_download_info(
+        &self,
+        game_url: &GameUrl,
+        upload_id: UploadId,
+        csrf_token: &str,
+    ) -> Result<DownloadInfo, Error> {
+        self.block_on(
+            self.client
+                .get_download_info(game_url, upload_id, csrf_token),
+        )
+    }
+
+    /// Get the download info for a given game upload by id,
+    /// using the latest csrf token for the session.
+    pub fn get_download_info_with_session(
+        &self,
+        game_url: &GameUrl,
+        upload_id: UploadId,
+    ) -> Result<DownloadInfo, Error> {
+        self.block_on(
+            self.client
+                .get_download_info_with_session(game_url, upload_id),
+        )
+    }
+
+    /// Get the purchase dialog for a game.
+    pub fn get_purchase_dialog(&self, game_url: &GameUrl) -> Result<PurchaseDialog, Error> {
+        self.block_on(self.client.get_purchase_dialog(game_url))
+    }
+
+    /// Get the download page url.
+    pub fn get_download_page_url(
+        &self,
+        game_url: &GameUrl,
+        csrf_token: &str,
+    ) -> Result<DownloadPageUrlInfo, Error> {
+        self.block_on(self.client.get_download_page_url(game_url, csrf_token))
+    }
+
+    /// Get the download page url, using the latest csrf token for the session.
+    pub fn get_download_page_url_with_session(
+        &self,
+        game_url: &GameUrl,
+    ) -> Result<DownloadPageUrlInfo, Error> {
+        self.block_on(self.client.get_download_page_url_with_session(game_url))
+    }
+
+    /// Get the download page from a url.
+    pub fn get_download_page(&self, url: &str) -> Result<DownloadPage, Error> {
+        self.block_on(self.client.get_download_page(url))
+    }
+
+    /// Get many download pages.
+    pub fn get_download_pages<I>(&self, urls: I) -> Batch<Url, DownloadPage>
+    where
+        I: IntoIterator<Item = Url>,
+    {
+        Batch {
+            batch: self.client.get_download_pages(urls),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Get the download page from a url, parsing it in lenient mode.
+    pub fn get_download_page_lenient(
+        &self,
+        url: &str,
+    ) -> Result<Parsed<DownloadPage, download_page::FromHtmlError>, Error> {
+        self.block_on(self.client.get_download_page_lenient(url))
+    }
+
+    /// Download a file to a path.
+    pub fn download<P>(&self, url: &Url, path: P) -> Download
+    where
+        P: Into<PathBuf>,
+    {
+        Download {
+            download: self.client.download(url, path),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Download every upload of a game into a directory, with a manifest.
+    #[cfg(feature = "serde")]
+    pub fn download_game<P>(&self, url: &GameUrl, dir: P) -> GameDownload
+    where
+        P: Into<PathBuf>,
+    {
+        GameDownload {
+            download: self.client.download_game(url, dir),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Download every upload of a game into a store.
+    #[cfg(feature = "serde")]
+    pub fn download_game_to_store(
+        &self,
+        url: &GameUrl,
+        store: &crate::store::Store,
+    ) -> GameDownload {
+        GameDownload {
+            download: self.client.download_game_to_store(url, store),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Get the info of an upload from the itch.io api.
+    pub fn get_upload_info(&self, upload_id: UploadId) -> Result<UploadInfo, Error> {
+        self.block_on(self.client.get_upload_info(upload_id))
+    }
+
+    /// Verify a downloaded upload.
+    pub fn verify_download<P>(&self, path: P, upload: &Upload) -> Result<Verification, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        self.block_on(self.client.verify_download(path, upload))
+    }
+
+    /// Get a page and check how well its parser does.
+    pub fn check_page(&self, kind: PageKind, url: &str) -> Result<DriftReport, Error> {
+        self.block_on(self.client.check_page(kind, url))
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::ReplayTransport;
+
+    #[test]
+    fn replay() {
+        let transport = ReplayTransport::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_data/cassettes/download_game_page_works.json"
+        ))
+        .expect("failed to load cassette");
+        let client = Client::with_transport(Arc::new(transport));
+
+        let url =
+            GameUrl::parse("https://jnohr.itch.io/mrk-borg-free").expect("failed to parse url");
+        let game_page = client.get_game_page(&url).expect("failed to get game page");
+        let id = game_page.uploads[0].id.expect("missing upload id");
+        let download_info = client
+            .get_download_info(&game_page.twitter_url, id, &game_page.csrf_token)
+            .expect("failed to get download info");
+        assert_eq!(
+            download_info.url.as_str(),
+            "https://files.example.com/mork-borg-free.pdf"
+        );
+    }
+}
@@ -1,122 +1,816 @@
+use crate::batch::Batch;
+use crate::download::verify_file;
+use crate::download::Download;
+use crate::download::DownloadError;
+use crate::download::ExpectedFile;
+use crate::download::RateLimiter;
+use crate::download::Verification;
+use crate::drift::DriftReport;
+use crate::drift::PageKind;
+use crate::executor::Executor;
+use crate::executor::TokioExecutor;
+#[cfg(feature = "serde")]
+use crate::game_download::GameDownload;
+use crate::metrics::Metrics;
+use crate::request;
+use crate::request::Endpoint;
+use crate::transport::Request;
+use crate::transport::ReqwestTransport;
+use crate::transport::Response;
+use crate::transport::ResponseUrl;
+use crate::transport::StreamingResponse;
+use crate::transport::Transport;
+use crate::types::download_page;
+use crate::types::game_page;
+use crate::types::game_url::ITCH_IO_DOMAIN;
 use crate::DownloadInfo;
 use crate::DownloadPage;
 use crate::DownloadPageUrlInfo;
 use crate::Error;
 use crate::GamePage;
+use crate::GameUrl;
+use crate::Parsed;
+use crate::PartialGamePage;
 use crate::PurchaseDialog;
+use crate::Selectors;
+use crate::Upload;
+use crate::UploadId;
+use crate::UploadInfo;
+use futures_util::StreamExt;
 use scraper::Html;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use url::Url;
 
 /// The client
 #[derive(Debug, Clone)]
 pub struct Client {
-    /// The inner http client.
+    /// The transport requests are sent through
+    transport: Arc<dyn Transport>,
+
+    /// The executor pages are parsed on, which also provides the timer
+    executor: Arc<dyn Executor>,
+
+    /// Limits the number of requests in flight, shared between clones
+    limiter: Option<Arc<Semaphore>>,
+
+    /// Limits the rate of downloads, shared between clones
+    rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// The metrics hook
+    metrics: Option<Arc<dyn Metrics>>,
+
+    /// The itch.io api key
+    api_key: Option<Arc<str>>,
+
+    /// The selectors used to parse pages.
     ///
-    /// Probably shouldn't be used by you.
-    pub client: reqwest::Client,
+    /// This is shared between clones, so that they can be replaced at runtime.
+    selectors: Arc<RwLock<Arc<Selectors>>>,
+
+    /// The latest csrf token of each session, keyed by session domain.
+    ///
+    /// This is shared between clones, as they share cookies.
+    csrf_tokens: Arc<RwLock<HashMap<String, String>>>,
 }
 
 impl Client {
     /// Make a new client
     pub fn new() -> Self {
+        Self::with_transport(Arc::new(ReqwestTransport::new()))
+    }
+
+    /// Make a new client that sends requests through the given transport.
+    ///
+    /// This can be used to record and replay requests for testing,
+    /// with [`crate::transport::RecordingTransport`] and [`crate::transport::ReplayTransport`],
+    /// or to cache responses, with [`crate::transport::CachingTransport`].
+    pub fn with_transport(transport: Arc<dyn Transport>) -> Self {
         Self {
-            client: reqwest::Client::builder()
-                .cookie_store(true)
-                .build()
-                .expect("failed to build itch.io client"),
+            transport,
+            executor: Arc::new(TokioExecutor),
+            limiter: None,
+            rate_limiter: None,
+            metrics: None,
+            api_key: None,
+            selectors: Arc::new(RwLock::new(Arc::new(Selectors::default()))),
+            csrf_tokens: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    /// Get a page and parse it
-    async fn get_html<F, T>(&self, url: &str, f: F) -> Result<T, Error>
+    /// Get the transport requests are sent through.
+    pub fn transport(&self) -> &Arc<dyn Transport> {
+        &self.transport
+    }
+
+    /// Set the executor pages are parsed on.
+    ///
+    /// Its timer is also used for download rate limits and download queue backoff.
+    /// This defaults to [`TokioExecutor`].
+    pub fn set_executor(&mut self, executor: Arc<dyn Executor>) {
+        self.executor = executor;
+    }
+
+    /// Limit the number of requests in flight.
+    ///
+    /// The limit is shared with clones made after this is called.
+    /// There is no limit by default.
+    pub fn set_max_concurrent_requests(&mut self, limit: usize) {
+        self.limiter = Some(Arc::new(Semaphore::new(limit.max(1))));
+    }
+
+    /// Limit the rate of downloads, in bytes per second.
+    ///
+    /// The limit is shared by all downloads of this client,
+    /// and with clones made after this is called.
+    /// There is no limit by default.
+    pub fn set_max_download_rate(&mut self, bytes_per_second: u64) {
+        self.rate_limiter = Some(Arc::new(RateLimiter::new(bytes_per_second)));
+    }
+
+    /// Get the download rate limiter.
+    pub(crate) fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_deref()
+    }
+
+    /// Set the metrics hook.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Set the itch.io api key.
+    ///
+    /// This is needed for [`Client::get_upload_info`],
+    /// and lets [`Client::verify_download`] check md5 hashes.
+    /// Keys can be made at <https://itch.io/user/settings/api-keys>.
+    pub fn set_api_key(&mut self, api_key: &str) {
+        self.api_key = Some(api_key.into());
+    }
+
+    /// Get the selectors used to parse pages.
+    pub fn selectors(&self) -> Arc<Selectors> {
+        self.selectors
+            .read()
+            .unwrap_or_else(|error| error.into_inner())
+            .clone()
+    }
+
+    /// Replace the selectors used to parse pages.
+    ///
+    /// This affects all clones of this client.
+    /// Pages that are already being parsed keep using the old selectors.
+    pub fn set_selectors(&self, selectors: Selectors) {
+        *self
+            .selectors
+            .write()
+            .unwrap_or_else(|error| error.into_inner()) = Arc::new(selectors);
+    }
+
+    /// Get the latest csrf token for the session a game page is fetched with.
+    ///
+    /// Tokens are recorded whenever a game page is fetched.
+    /// All games on itch.io share a session, while each custom domain has its own.
+    pub fn csrf_token(&self, game_url: &GameUrl) -> Option<String> {
+        self.csrf_tokens
+            .read()
+            .unwrap_or_else(|error| error.into_inner())
+            .get(session_domain(game_url))
+            .cloned()
+    }
+
+    /// Set the csrf token for the session a game page is fetched with.
+    ///
+    /// This affects all clones of this client.
+    pub fn set_csrf_token(&self, game_url: &GameUrl, csrf_token: String) {
+        self.csrf_tokens
+            .write()
+            .unwrap_or_else(|error| error.into_inner())
+            .insert(session_domain(game_url).to_string(), csrf_token);
+    }
+
+    /// Fetch a game page to get a fresh csrf token for its session.
+    ///
+    /// The request is sent with `Cache-Control: no-cache`,
+    /// so that [`CachingTransport`](crate::transport::CachingTransport) does not serve a stale token.
+    pub async fn refresh_csrf_token(&self, game_url: &GameUrl) -> Result<String, Error> {
+        let mut request = request::game_page(game_url)?;
+        request.headers_mut().insert(
+            http::header::CACHE_CONTROL,
+            http::HeaderValue::from_static("no-cache"),
+        );
+        let game_page = self.fetch_game_page(request).await?;
+        Ok(game_page.csrf_token)
+    }
+
+    /// Run a post request with the latest csrf token for a session.
+    ///
+    /// If there is no token yet, one is fetched first.
+    /// If the request is rejected, the token is refreshed and the request is retried once.
+    async fn with_csrf_token<F, Fut, T>(
+        &self,
+        game_url: &GameUrl,
+        endpoint: Endpoint,
+        f: F,
+    ) -> Result<T, Error>
     where
-        F: FnOnce(Html) -> T + Send + 'static,
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let csrf_token = match self.csrf_token(game_url) {
+            Some(csrf_token) => csrf_token,
+            None => self.refresh_csrf_token(game_url).await?,
+        };
+
+        match f(csrf_token).await {
+            Err(error) if is_rejection(&error) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(?endpoint, %error, "request rejected, refreshing csrf token");
+                if let Some(metrics) = self.metrics.as_ref() {
+                    metrics.retry(Some(endpoint));
+                }
+
+                let csrf_token = self.refresh_csrf_token(game_url).await?;
+                f(csrf_token).await
+            }
+            result => result,
+        }
+    }
+
+    /// Send a request, failing on error status codes.
+    ///
+    /// Returns the final url of the response, after redirects, and the body.
+    async fn send(&self, request: Request) -> Result<(Url, Vec<u8>), Error> {
+        let (url, response) = self.send_response(request).await?;
+        Ok((url, response.into_body()))
+    }
+
+    /// Send a request, failing on error status codes.
+    ///
+    /// Returns the final url of the response, after redirects, and the response.
+    pub(crate) async fn send_response(&self, request: Request) -> Result<(Url, Response), Error> {
+        let url = Url::parse(&request.uri().to_string())?;
+        let endpoint = Endpoint::of(&request);
+
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "request",
+            method = %request.method(),
+            url = %url,
+            ?endpoint,
+            status = tracing::field::Empty,
+            bytes = tracing::field::Empty,
+        );
+
+        let _permit = match self.limiter.as_ref() {
+            Some(limiter) => limiter.acquire().await.ok(),
+            None => None,
+        };
+
+        let start = Instant::now();
+        let response = self.transport.send(request);
+        #[cfg(feature = "tracing")]
+        let response = tracing::Instrument::instrument(response, span.clone());
+        let response = response.await;
+        let duration = start.elapsed();
+
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(parent: &span, ?duration, %error, "request failed");
+                if let Some(metrics) = self.metrics.as_ref() {
+                    metrics.request(endpoint, None, 0, duration);
+                }
+                return Err(error);
+            }
+        };
+
+        let status = response.status();
+        let bytes = response.body().len();
+        #[cfg(feature = "tracing")]
+        {
+            span.record("status", status.as_u16());
+            span.record("bytes", bytes);
+            tracing::debug!(parent: &span, ?duration, "received response");
+        }
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.request(endpoint, Some(status), bytes, duration);
+        }
+
+        let url = response
+            .extensions()
+            .get::<ResponseUrl>()
+            .map_or(url, |url| url.0.clone());
+        if status.is_client_error() || status.is_server_error() {
+            return Err(Error::InvalidStatus { url, status });
+        }
+
+        Ok((url, response))
+    }
+
+    /// Send a request, without waiting for the body.
+    ///
+    /// Returns the final url of the response, after redirects, and the response.
+    /// Unlike [`Client::send_response`], this does not fail on error status codes,
+    /// as some of them are expected while downloading.
+    /// The request limit is held until the body is dropped.
+    pub(crate) async fn send_streaming(
+        &self,
+        request: Request,
+    ) -> Result<(Url, StreamingResponse), Error> {
+        let url = Url::parse(&request.uri().to_string())?;
+        let endpoint = Endpoint::of(&request);
+
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "request",
+            method = %request.method(),
+            url = %url,
+            ?endpoint,
+            status = tracing::field::Empty,
+        );
+
+        let permit = match self.limiter.clone() {
+            Some(limiter) => limiter.acquire_owned().await.ok(),
+            None => None,
+        };
+
+        let start = Instant::now();
+        let response = self.transport.send_streaming(request);
+        #[cfg(feature = "tracing")]
+        let response = tracing::Instrument::instrument(response, span.clone());
+        let response = response.await;
+        let duration = start.elapsed();
+
+        // The body has not been read yet, so the size is only known from the headers.
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(parent: &span, ?duration, %error, "request failed");
+                if let Some(metrics) = self.metrics.as_ref() {
+                    metrics.request(endpoint, None, 0, duration);
+                }
+                return Err(error);
+            }
+        };
+        let status = response.status();
+        #[cfg(feature = "tracing")]
+        {
+            span.record("status", status.as_u16());
+            tracing::debug!(parent: &span, ?duration, "received response headers");
+        }
+        if let Some(metrics) = self.metrics.as_ref() {
+            let bytes = response
+                .headers()
+                .get(http::header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .unwrap_or(0);
+            metrics.request(endpoint, Some(status), bytes, duration);
+        }
+
+        let url = response
+            .extensions()
+            .get::<ResponseUrl>()
+            .map_or(url, |url| url.0.clone());
+        let response = response.map(|body| {
+            body.map(move |chunk| {
+                let _permit = &permit;
+                chunk
+            })
+            .boxed()
+        });
+
+        Ok((url, response))
+    }
+
+    /// Wait for a duration, using the timer of the executor.
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        self.executor.sleep(duration).await;
+    }
+
+    /// Run a blocking function on the executor.
+    pub(crate) async fn spawn_blocking<F, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> T + Send + 'static,
         T: Send + 'static,
     {
-        let text = self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .error_for_status()?
-            .text()
+        let result = Arc::new(Mutex::new(None));
+        let task_result = result.clone();
+        self.executor
+            .spawn_blocking(Box::new(move || {
+                let value = f();
+                *task_result
+                    .lock()
+                    .unwrap_or_else(|error| error.into_inner()) = Some(value);
+            }))
+            .await
+            .map_err(Error::Executor)?;
+
+        let value = result
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .take()
+            .ok_or(Error::TaskIncomplete)?;
+        Ok(value)
+    }
+
+    /// Get a page and parse it.
+    ///
+    /// `f` is passed the final url of the page, after redirects.
+    async fn get_html<F, T>(&self, kind: PageKind, request: Request, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(Url, Html) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (url, body) = self.send(request).await?;
+
+        let (value, duration) = self
+            .spawn_blocking(move || {
+                let start = Instant::now();
+                let html = Html::parse_document(&String::from_utf8_lossy(&body));
+                (f(url, html), start.elapsed())
+            })
             .await?;
 
-        Ok(tokio::task::spawn_blocking(move || {
-            let html = Html::parse_document(&text);
-            f(html)
-        })
-        .await?)
+        #[cfg(feature = "tracing")]
+        tracing::debug!(page = %kind, ?duration, "parsed page");
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.parse(kind, duration);
+        }
+
+        match value.as_ref() {
+            Err(Error::InvalidGamePage { error, .. }) => {
+                self.report_parse_failures(kind, [(error.field.as_str(), error.error.name())]);
+            }
+            Err(Error::InvalidDownloadPage { error, .. }) => {
+                self.report_parse_failures(kind, [(error.field.as_str(), error.error.name())]);
+            }
+            _ => {}
+        }
+
+        value
+    }
+
+    /// Report problems found while parsing a page.
+    ///
+    /// `failures` are pairs of the field and the name of the error variant.
+    fn report_parse_failures<'a, I>(&self, kind: PageKind, failures: I)
+    where
+        I: IntoIterator<Item = (&'a str, &'static str)>,
+    {
+        for (_field, error) in failures {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(page = %kind, field = _field, error, "failed to parse field");
+            if let Some(metrics) = self.metrics.as_ref() {
+                metrics.parse_failure(kind, error);
+            }
+        }
     }
 
     /// Get a game page.
     ///
     /// The `url` parameter should be a url for the game page, like `https://tumblewed.itch.io/doghouse-2`.
-    pub async fn get_game_page(&self, url: &str) -> Result<GamePage, Error> {
-        Ok(self
-            .get_html(url, |html| GamePage::from_html(&html))
-            .await??)
+    /// Games on custom domains are supported.
+    pub async fn get_game_page(&self, url: &GameUrl) -> Result<GamePage, Error> {
+        self.fetch_game_page(request::game_page(url)?).await
+    }
+
+    /// Send a game page request and parse the response, recording its csrf token.
+    async fn fetch_game_page(&self, request: Request) -> Result<GamePage, Error> {
+        let selectors = self.selectors();
+        let game_page = self
+            .get_html(PageKind::GamePage, request, move |url, html| {
+                GamePage::from_html_with(&html, &selectors).map_err(|error| {
+                    Error::InvalidGamePage {
+                        url,
+                        error: Box::new(error),
+                    }
+                })
+            })
+            .await?;
+        self.set_csrf_token(&game_page.twitter_url, game_page.csrf_token.clone());
+        Ok(game_page)
+    }
+
+    /// Get a game page, parsing it in lenient mode.
+    ///
+    /// See [`GamePage::from_html_lenient`].
+    pub async fn get_game_page_lenient(
+        &self,
+        url: &GameUrl,
+    ) -> Result<Parsed<PartialGamePage, game_page::FromHtmlError>, Error> {
+        let request = request::game_page(url)?;
+        let selectors = self.selectors();
+        let parsed = self
+            .get_html(PageKind::GamePage, request, move |_url, html| {
+                Ok(GamePage::from_html_lenient_with(&html, &selectors))
+            })
+            .await?;
+        if let (Some(twitter_url), Some(csrf_token)) = (
+            parsed.value.twitter_url.as_ref(),
+            parsed.value.csrf_token.as_ref(),
+        ) {
+            self.set_csrf_token(twitter_url, csrf_token.clone());
+        }
+        self.report_parse_failures(
+            PageKind::GamePage,
+            parsed
+                .warnings
+                .iter()
+                .map(|warning| (warning.field.as_str(), warning.error.name())),
+        );
+        Ok(parsed)
+    }
+
+    /// Get many game pages.
+    ///
+    /// See [`Batch`] for how requests are scheduled.
+    pub fn get_game_pages<I>(&self, urls: I) -> Batch<GameUrl, GamePage>
+    where
+        I: IntoIterator<Item = GameUrl>,
+    {
+        let client = self.clone();
+        Batch::new(
+            urls,
+            |url| url.as_url().host_str().unwrap_or_default().to_string(),
+            Arc::new(move |url| {
+                let client = client.clone();
+                Box::pin(async move { client.get_game_page(&url).await })
+            }),
+        )
     }
 
-    /// Get the download info for a given game download by id.
+    /// Get the download info for a given game upload by id.
+    ///
+    /// For games on custom domains, `game_url` should be the `twitter_url` of the game page,
+    /// as the csrf token is tied to the domain the game page was fetched from.
     pub async fn get_download_info(
         &self,
-        game_page_url: &str,
-        download_id: u64,
+        game_url: &GameUrl,
+        upload_id: UploadId,
         csrf_token: &str,
     ) -> Result<DownloadInfo, Error> {
-        let url = format!("{game_page_url}/file/{download_id}?after_download_lightbox=true");
+        let request = request::download_info(game_url, upload_id, csrf_token)?;
+        let (url, body) = self.send(request).await?;
+        parse_json(url, &body, DownloadInfo::from_json)
+    }
 
-        Ok(self
-            .client
-            .post(url.as_str())
-            .form(&[("csrf_token", csrf_token)])
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?)
+    /// Get the download info for a given game upload by id,
+    /// using the latest csrf token for the session.
+    ///
+    /// See [`Client::csrf_token`].
+    /// If the request is rejected, the token is refreshed and the request is retried once.
+    pub async fn get_download_info_with_session(
+        &self,
+        game_url: &GameUrl,
+        upload_id: UploadId,
+    ) -> Result<DownloadInfo, Error> {
+        self.with_csrf_token(game_url, Endpoint::DownloadInfo, |csrf_token| async move {
+            self.get_download_info(game_url, upload_id, &csrf_token)
+                .await
+        })
+        .await
     }
 
     /// Get the purchase dialog for a game.
     ///
     /// This is the download that appears when clicking "download now".
-    pub async fn get_purchase_dialog(&self, game_page_url: &str) -> Result<PurchaseDialog, Error> {
-        let url = format!("{game_page_url}/purchase?lightbox=true");
-        Ok(self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?)
+    pub async fn get_purchase_dialog(&self, game_url: &GameUrl) -> Result<PurchaseDialog, Error> {
+        let request = request::purchase_dialog(game_url)?;
+        let (url, body) = self.send(request).await?;
+        parse_json(url, &body, PurchaseDialog::from_json)
     }
 
-    /// Get the download page url
+    /// Get the download page url.
+    ///
+    /// For games on custom domains, `game_url` should be the `twitter_url` of the game page,
+    /// as the csrf token is tied to the domain the game page was fetched from.
     pub async fn get_download_page_url(
         &self,
-        game_page_url: &str,
+        game_url: &GameUrl,
         csrf_token: &str,
     ) -> Result<DownloadPageUrlInfo, Error> {
-        let url = format!("{game_page_url}/download_url");
-        Ok(self
-            .client
-            .post(url)
-            .form(&[("csrf_token", csrf_token)])
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?)
+        let request = request::download_page_url(game_url, csrf_token)?;
+        let (url, body) = self.send(request).await?;
+        parse_json(url, &body, DownloadPageUrlInfo::from_json)
+    }
+
+    /// Get the download page url, using the latest csrf token for the session.
+    ///
+    /// See [`Client::csrf_token`].
+    /// If the request is rejected, the token is refreshed and the request is retried once.
+    pub async fn get_download_page_url_with_session(
+        &self,
+        game_url: &GameUrl,
+    ) -> Result<DownloadPageUrlInfo, Error> {
+        self.with_csrf_token(
+            game_url,
+            Endpoint::DownloadPageUrl,
+            |csrf_token| async move { self.get_download_page_url(game_url, &csrf_token).await },
+        )
+        .await
     }
 
     /// Get the download page from a url.
     ///
     /// The url must be from the `DownloadPageUrlInfo` struct.
     pub async fn get_download_page(&self, url: &str) -> Result<DownloadPage, Error> {
-        Ok(self
-            .get_html(url, |html| DownloadPage::from_html(&html))
-            .await??)
+        let request = request::download_page(&Url::parse(url)?)?;
+        let selectors = self.selectors();
+        self.get_html(PageKind::DownloadPage, request, move |url, html| {
+            DownloadPage::from_html_with(&html, &selectors).map_err(|error| {
+                Error::InvalidDownloadPage {
+                    url,
+                    error: Box::new(error),
+                }
+            })
+        })
+        .await
+    }
+
+    /// Get many download pages.
+    ///
+    /// See [`Batch`] for how requests are scheduled.
+    pub fn get_download_pages<I>(&self, urls: I) -> Batch<Url, DownloadPage>
+    where
+        I: IntoIterator<Item = Url>,
+    {
+        let client = self.clone();
+        Batch::new(
+            urls,
+            |url| url.host_str().unwrap_or_default().to_string(),
+            Arc::new(move |url| {
+                let client = client.clone();
+                Box::pin(async move { client.get_download_page(url.as_str()).await })
+            }),
+        )
+    }
+
+    /// Get the download page from a url, parsing it in lenient mode.
+    ///
+    /// See [`DownloadPage::from_html_lenient`].
+    pub async fn get_download_page_lenient(
+        &self,
+        url: &str,
+    ) -> Result<Parsed<DownloadPage, download_page::FromHtmlError>, Error> {
+        let request = request::download_page(&Url::parse(url)?)?;
+        let selectors = self.selectors();
+        let parsed = self
+            .get_html(PageKind::DownloadPage, request, move |_url, html| {
+                Ok(DownloadPage::from_html_lenient_with(&html, &selectors))
+            })
+            .await?;
+        self.report_parse_failures(
+            PageKind::DownloadPage,
+            parsed
+                .warnings
+                .iter()
+                .map(|warning| (warning.field.as_str(), warning.error.name())),
+        );
+        Ok(parsed)
+    }
+
+    /// Download a file to a path.
+    ///
+    /// The url must be from the `DownloadInfo` struct.
+    /// See [`Download`] for options.
+    pub fn download<P>(&self, url: &Url, path: P) -> Download
+    where
+        P: Into<PathBuf>,
+    {
+        Download::new(self.clone(), url.clone(), path.into())
+    }
+
+    /// Download every upload of a game into a directory, with a manifest.
+    ///
+    /// See [`GameDownload`] for options.
+    #[cfg(feature = "serde")]
+    pub fn download_game<P>(&self, url: &GameUrl, dir: P) -> GameDownload
+    where
+        P: Into<PathBuf>,
+    {
+        GameDownload::new(self.clone(), url.clone(), dir.into())
+    }
+
+    /// Download every upload of a game into a store.
+    ///
+    /// Uploads the store already has are linked instead of downloaded.
+    /// They are recognized by the game's last manifest, if their md5 hash from the itch.io api matches,
+    /// or without an api key, if the upload looks unchanged since.
+    /// See [`Store`](crate::store::Store) for the layout.
+    #[cfg(feature = "serde")]
+    pub fn download_game_to_store(
+        &self,
+        url: &GameUrl,
+        store: &crate::store::Store,
+    ) -> GameDownload {
+        GameDownload::new_in_store(self.clone(), url.clone(), store.clone())
+    }
+
+    /// Get the info of an upload from the itch.io api.
+    ///
+    /// This fails with [`Error::MissingApiKey`] if no api key was set.
+    pub async fn get_upload_info(&self, upload_id: UploadId) -> Result<UploadInfo, Error> {
+        let api_key = self.api_key.as_deref().ok_or(Error::MissingApiKey)?;
+        let request = request::upload_info(upload_id, api_key)?;
+        let (url, body) = self.send(request).await?;
+        parse_json(url, &body, UploadInfo::from_json)
+    }
+
+    /// Get the info of an upload from the itch.io api, if the api has it.
+    ///
+    /// Returns `None` if no api key was set,
+    /// or if the api does not know the upload or rejects the key for it.
+    /// Other errors, like failing to connect, are returned.
+    pub(crate) async fn get_upload_info_if_available(
+        &self,
+        upload_id: UploadId,
+    ) -> Result<Option<UploadInfo>, Error> {
+        match self.get_upload_info(upload_id).await {
+            Ok(upload_info) => Ok(Some(upload_info)),
+            Err(
+                Error::MissingApiKey
+                | Error::InvalidStatus { .. }
+                | Error::Rejected { .. }
+                | Error::InvalidJson { .. },
+            ) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Verify a downloaded upload.
+    ///
+    /// If the itch.io api has the upload,
+    /// the file is checked against its md5 hash and exact size.
+    /// Otherwise, like when no api key is set or the key cannot see the upload,
+    /// it is checked against the scraped size of the upload.
+    pub async fn verify_download<P>(&self, path: P, upload: &Upload) -> Result<Verification, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let mut expected = ExpectedFile {
+            scraped_size: upload.size,
+            ..ExpectedFile::default()
+        };
+        if let Some(upload_id) = upload.id {
+            if let Some(upload_info) = self.get_upload_info_if_available(upload_id).await? {
+                expected.md5_hash = upload_info.md5_hash;
+                expected.size = upload_info.size;
+            }
+        }
+
+        let path = path.into();
+        let verification = self
+            .spawn_blocking(move || verify_file(path, &expected))
+            .await?
+            .map_err(DownloadError::from)?;
+        Ok(verification)
+    }
+
+    /// Get a page and check how well its parser does.
+    ///
+    /// For [`PageKind::Lightbox`], `url` is a game page,
+    /// and the lightbox of its first upload is checked.
+    ///
+    /// See [`crate::drift`].
+    pub async fn check_page(&self, kind: PageKind, url: &str) -> Result<DriftReport, Error> {
+        if kind == PageKind::Lightbox {
+            return self.check_lightbox(&GameUrl::parse(url)?).await;
+        }
+
+        let request = request::page(&Url::parse(url)?)?;
+        let selectors = self.selectors();
+        self.get_html(kind, request, move |_url, html| {
+            let mut report = DriftReport::new();
+            report.check_page(kind, &html, &selectors);
+            Ok(report)
+        })
+        .await
+    }
+
+    /// Get the lightbox of the first upload of a game and check how well its parser does.
+    ///
+    /// The report is empty if the game has no uploads with ids.
+    async fn check_lightbox(&self, game_url: &GameUrl) -> Result<DriftReport, Error> {
+        let game_page = self.get_game_page(game_url).await?;
+        let mut report = DriftReport::new();
+        let Some(upload_id) = game_page.uploads.iter().find_map(|upload| upload.id) else {
+            return Ok(report);
+        };
+        let download_info = self
+            .get_download_info_with_session(&game_page.twitter_url, upload_id)
+            .await?;
+        let html = Html::parse_fragment(&download_info.lightbox);
+        report.check_page(PageKind::Lightbox, &html, &self.selectors());
+        Ok(report)
     }
 }
 
@@ -125,3 +819,144 @@ impl Default for Client {
         Self::new()
     }
 }
+
+/// Get the domain whose session a game page is fetched with.
+///
+/// Cookies for itch.io are shared by all of its subdomains.
+fn session_domain(game_url: &GameUrl) -> &str {
+    if game_url.is_custom_domain() {
+        game_url.as_url().host_str().unwrap_or_default()
+    } else {
+        ITCH_IO_DOMAIN
+    }
+}
+
+/// Returns true if an error means a post request was rejected, likely due to a stale csrf token.
+fn is_rejection(error: &Error) -> bool {
+    match error {
+        Error::Rejected { .. } => true,
+        Error::InvalidStatus { status, .. } => matches!(status.as_u16(), 403 | 419 | 422),
+        _ => false,
+    }
+}
+
+/// Parse a json response, turning a list of errors into [`Error::Rejected`].
+///
+/// Other failures are returned as [`Error::InvalidJson`] with the url of the response.
+fn parse_json<T>(
+    url: Url,
+    body: &[u8],
+    f: fn(&[u8]) -> Result<T, serde_json::Error>,
+) -> Result<T, Error> {
+    /// A json error response
+    #[derive(serde::Deserialize)]
+    struct ErrorResponse {
+        errors: Vec<String>,
+    }
+
+    f(body).map_err(
+        |error| match serde_json::from_slice::<ErrorResponse>(body) {
+            Ok(response) => Error::Rejected {
+                url,
+                errors: response.errors,
+            },
+            Err(_) => Error::InvalidJson { url, error },
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::metrics::Counters;
+    use crate::transport::BodyEncoding;
+    use crate::transport::Cassette;
+    use crate::transport::Interaction;
+    use crate::transport::RecordedRequest;
+    use crate::transport::RecordedResponse;
+    use crate::transport::ReplayTransport;
+
+    #[tokio::test]
+    async fn stale_csrf_token() {
+        let mut cassette = Cassette::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_data/cassettes/download_game_page_works.json"
+        ))
+        .expect("failed to load cassette");
+        cassette.interactions.insert(
+            0,
+            Interaction {
+                request: RecordedRequest {
+                    method: "POST".into(),
+                    uri:
+                        "https://jnohr.itch.io/mrk-borg-free/file/2002?after_download_lightbox=true"
+                            .into(),
+                    body: "csrf_token=stale".into(),
+                },
+                response: RecordedResponse {
+                    status: 200,
+                    url: None,
+                    headers: Vec::new(),
+                    body: r#"{"errors": ["invalid csrf token"]}"#.into(),
+                    body_encoding: BodyEncoding::Utf8,
+                },
+            },
+        );
+        let counters = Arc::new(Counters::new());
+        let mut client = Client::with_transport(Arc::new(ReplayTransport::new(cassette)));
+        client.set_metrics(counters.clone());
+
+        let url =
+            GameUrl::parse("https://jnohr.itch.io/mrk-borg-free").expect("failed to parse url");
+        client.set_csrf_token(&url, "stale".into());
+        let download_info = client
+            .get_download_info_with_session(&url, UploadId(2002))
+            .await
+            .expect("failed to get download info");
+        assert_eq!(
+            download_info.url.as_str(),
+            "https://files.example.com/mork-borg-free.pdf"
+        );
+        assert_eq!(counters.retries(), 1);
+        assert_eq!(client.csrf_token(&url).as_deref(), Some("token-2"));
+
+        // Games on itch.io share a session.
+        let other_url =
+            GameUrl::parse("https://tumblewed.itch.io/doghouse-2").expect("failed to parse url");
+        assert_eq!(client.csrf_token(&other_url).as_deref(), Some("token-2"));
+    }
+
+    #[tokio::test]
+    async fn invalid_json_has_url() {
+        let uri = "https://jnohr.itch.io/mrk-borg-free/file/2002?after_download_lightbox=true";
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                request: RecordedRequest {
+                    method: "POST".into(),
+                    uri: uri.into(),
+                    body: "csrf_token=token".into(),
+                },
+                response: RecordedResponse {
+                    status: 200,
+                    url: None,
+                    headers: Vec::new(),
+                    body: "<html></html>".into(),
+                    body_encoding: BodyEncoding::Utf8,
+                },
+            }],
+        };
+        let client = Client::with_transport(Arc::new(ReplayTransport::new(cassette)));
+
+        let url =
+            GameUrl::parse("https://jnohr.itch.io/mrk-borg-free").expect("failed to parse url");
+        client.set_csrf_token(&url, "token".into());
+        let error = client
+            .get_download_info_with_session(&url, UploadId(2002))
+            .await
+            .expect_err("invalid json was accepted");
+        assert!(
+            matches!(&error, Error::InvalidJson { url, .. } if url.as_str() == uri),
+            "{error:?}"
+        );
+    }
+}
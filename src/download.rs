@@ -0,0 +1,1060 @@
+//! File downloads.
+//!
+//! Start a download with [`Client::download`](crate::Client::download).
+//! Files are fetched in ranged chunks when the server supports it,
+//! optionally over several connections at once.
+//! Servers that do not support ranges are downloaded in one request.
+//! Either way, bodies are written to the file as they arrive, so files are never held in memory.
+//!
+//! Downloads are bounded by the client-wide rate set with
+//! [`Client::set_max_download_rate`](crate::Client::set_max_download_rate),
+//! which is applied after every read and waits with the timer of the client's
+//! [`Executor`](crate::executor::Executor).
+//!
+//! Downloaded files can be checked with
+//! [`Client::verify_download`](crate::Client::verify_download) or [`verify_file`].
+
+use crate::request;
+use crate::transport::BodyStream;
+use crate::transport::StreamingResponse;
+use crate::ByteSize;
+use crate::Client;
+use crate::Error;
+use futures_util::future::AbortHandle;
+use futures_util::future::AbortRegistration;
+use futures_util::future::Abortable;
+use futures_util::StreamExt;
+use md5::Digest;
+use md5::Md5;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use url::Url;
+
+/// The default size of a chunk
+const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// How many received bytes are buffered before writing them to the file
+const WRITE_BUFFER_SIZE: usize = 256 * 1024;
+
+/// An error that may occur while downloading a file
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    /// Failed to write the file
+    #[error("failed to write file")]
+    Io(#[from] std::io::Error),
+
+    /// The download was cancelled
+    #[error("download cancelled")]
+    Cancelled,
+
+    /// The server sent a missing or invalid `Content-Range` header
+    #[error("invalid content range")]
+    InvalidContentRange,
+
+    /// The server sent a chunk of the wrong size
+    #[error("expected {expected} bytes at offset {offset}, got {received}")]
+    InvalidChunk {
+        /// The offset of the chunk
+        offset: u64,
+
+        /// The requested number of bytes
+        expected: u64,
+
+        /// The received number of bytes
+        received: u64,
+    },
+}
+
+/// A handle to cancel a download.
+///
+/// This can be cloned and sent to other tasks.
+#[derive(Debug, Clone)]
+pub struct CancelHandle(AbortHandle);
+
+impl CancelHandle {
+    /// Cancel the download.
+    ///
+    /// Requests in flight are dropped and the download fails with [`DownloadError::Cancelled`].
+    pub fn cancel(&self) {
+        self.0.abort();
+    }
+}
+
+/// Limits the rate of downloads, shared by all downloads of a client
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// The max number of bytes per second
+    bytes_per_second: u64,
+
+    /// When the next read may happen
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Make a new rate limiter.
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Account for `bytes` more bytes, returning how long to wait before reading more.
+    fn reserve(&self, bytes: u64) -> Duration {
+        let mut next = self.next.lock().unwrap_or_else(|error| error.into_inner());
+        let now = Instant::now();
+        *next =
+            (*next).max(now) + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        next.saturating_duration_since(now)
+    }
+}
+
+/// A function called with the download progress
+pub type ProgressFn = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// A function called with what the server reports about a file
+pub type RemoteFileFn = Arc<dyn Fn(&RemoteFile) + Send + Sync>;
+
+/// What the server reports about a file.
+///
+/// This is used to check that a resumed download is still the same file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemoteFile {
+    /// The total size
+    pub size: Option<u64>,
+
+    /// The `ETag` header
+    pub etag: Option<String>,
+
+    /// The `Last-Modified` header
+    pub last_modified: Option<String>,
+}
+
+impl RemoteFile {
+    /// Read what a response says about the file, given the total size if it is known.
+    fn from_response(response: &StreamingResponse, size: Option<u64>) -> Self {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &http::HeaderValue| value.to_str().ok())
+                .map(String::from)
+        };
+        Self {
+            size,
+            etag: header(http::header::ETAG),
+            last_modified: header(http::header::LAST_MODIFIED),
+        }
+    }
+
+    /// Get the value to send in an `If-Range` header.
+    ///
+    /// Weak etags cannot be used for ranges, so this falls back to `Last-Modified`.
+    fn validator(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+
+    /// Returns true if nothing known about the two files differs.
+    fn matches(&self, other: &Self) -> bool {
+        fn same<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+        }
+        same(&self.size, &other.size)
+            && same(&self.etag, &other.etag)
+            && same(&self.last_modified, &other.last_modified)
+    }
+}
+
+/// A file download.
+///
+/// Call [`Download::run`] to start it.
+pub struct Download {
+    /// The client requests are sent with
+    client: Client,
+
+    /// The download url
+    url: Url,
+
+    /// The path to write to
+    path: PathBuf,
+
+    /// The max number of chunks in flight
+    connections: usize,
+
+    /// The size of a chunk
+    chunk_size: u64,
+
+    /// The offset to resume from
+    offset: u64,
+
+    /// Called with the download progress
+    progress: Option<ProgressFn>,
+
+    /// What the file looked like when the partial download was made
+    expected: RemoteFile,
+
+    /// Called with what the server reports about the file
+    on_remote_file: Option<RemoteFileFn>,
+
+    /// Cancels the download
+    abort_handle: AbortHandle,
+
+    /// Lets the download be cancelled
+    abort_registration: AbortRegistration,
+}
+
+impl Download {
+    /// Make a new download.
+    pub(crate) fn new(client: Client, url: Url, path: PathBuf) -> Self {
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        Self {
+            client,
+            url,
+            path,
+            connections: 1,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            offset: 0,
+            progress: None,
+            expected: RemoteFile::default(),
+            on_remote_file: None,
+            abort_handle,
+            abort_registration,
+        }
+    }
+
+    /// Set the max number of chunks in flight.
+    ///
+    /// This defaults to 1.
+    /// Requests are also bounded by the client-wide limit set with
+    /// [`Client::set_max_concurrent_requests`].
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.connections = connections.max(1);
+        self
+    }
+
+    /// Set the size of a chunk, in bytes.
+    ///
+    /// This defaults to 8 MiB.
+    /// Chunks are streamed to the file as they arrive, so they are never held in memory whole.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Resume a partial download, keeping the first `offset` bytes of the file.
+    ///
+    /// Anything after `offset` is discarded.
+    /// If the server does not support ranges, the whole file is downloaded again.
+    /// Use [`Download::if_unchanged`] to make sure the file has not changed since.
+    pub fn resume_from(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Only resume if the file on the server still matches what was reported when the partial download was made.
+    ///
+    /// The ranges are sent with an `If-Range` header,
+    /// and the size and validators of the response are checked too.
+    /// If anything differs, the download starts over from the beginning.
+    pub fn if_unchanged(mut self, expected: RemoteFile) -> Self {
+        self.expected = expected;
+        self
+    }
+
+    /// Set a function to call with what the server reports about the file, once the download starts.
+    ///
+    /// Keep it to resume later with [`Download::if_unchanged`].
+    pub fn with_remote_file(mut self, on_remote_file: RemoteFileFn) -> Self {
+        self.on_remote_file = Some(on_remote_file);
+        self
+    }
+
+    /// Set a function to call with the download progress.
+    ///
+    /// It is passed the number of bytes from the start of the file that have been written,
+    /// which is a safe offset to resume from with [`Download::resume_from`].
+    /// With several connections, chunks past a gap are not counted until the gap is filled.
+    pub fn with_progress(mut self, progress: ProgressFn) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Get a handle to cancel this download.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle(self.abort_handle.clone())
+    }
+
+    /// Run the download, returning the size of the file.
+    ///
+    /// The file is created or truncated, unless resuming.
+    /// If the download fails or is cancelled, the partial file is left in place.
+    pub async fn run(self) -> Result<u64, Error> {
+        let abort_registration = self.abort_registration;
+        let job = Job {
+            client: self.client,
+            url: self.url,
+            path: self.path,
+            connections: self.connections,
+            chunk_size: self.chunk_size,
+            offset: self.offset,
+            progress: self.progress,
+            expected: self.expected,
+            on_remote_file: self.on_remote_file,
+        };
+        match Abortable::new(async move { job.run().await }, abort_registration).await {
+            Ok(result) => result,
+            Err(_aborted) => Err(DownloadError::Cancelled.into()),
+        }
+    }
+}
+
+impl std::fmt::Debug for Download {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Download")
+            .field("url", &self.url.as_str())
+            .field("path", &self.path)
+            .field("connections", &self.connections)
+            .field("chunk_size", &self.chunk_size)
+            .field("offset", &self.offset)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The options of a running download
+struct Job {
+    /// The client requests are sent with
+    client: Client,
+
+    /// The download url
+    url: Url,
+
+    /// The path to write to
+    path: PathBuf,
+
+    /// The max number of chunks in flight
+    connections: usize,
+
+    /// The size of a chunk
+    chunk_size: u64,
+
+    /// The offset to resume from
+    offset: u64,
+
+    /// Called with the download progress
+    progress: Option<ProgressFn>,
+
+    /// What the file looked like when the partial download was made
+    expected: RemoteFile,
+
+    /// Called with what the server reports about the file
+    on_remote_file: Option<RemoteFileFn>,
+}
+
+impl Job {
+    /// Download the file.
+    async fn run(&self) -> Result<u64, Error> {
+        let mut offset = self.offset;
+        let if_range = self.expected.validator().filter(|_| offset > 0);
+        let mut first = self.fetch(Some(self.range_at(offset)), if_range).await?;
+        if first.status() == http::StatusCode::RANGE_NOT_SATISFIABLE {
+            let total = unsatisfied_range_total(&first);
+            let remote = RemoteFile::from_response(&first, total);
+            match total {
+                // The file was already complete.
+                Some(total) if total == offset && self.expected.matches(&remote) => {
+                    self.open(total).await?;
+                    self.found(&remote);
+                    self.report(total);
+                    return Ok(total);
+                }
+
+                // The file is not the one that was partially downloaded, so start over.
+                // Responses hold a request permit until dropped, so drop them before refetching.
+                Some(total) if total > 0 => {
+                    drop(first);
+                    offset = 0;
+                    first = self.fetch(Some(self.range_at(offset)), None).await?;
+                }
+
+                // Empty files cannot satisfy any range.
+                _ => {
+                    drop(first);
+                    first = self.fetch(None, None).await?;
+                }
+            }
+        }
+        self.check_status(&first)?;
+
+        // Servers that ignore `If-Range` still report the size and validators of the file.
+        if first.status() == http::StatusCode::PARTIAL_CONTENT && offset > 0 {
+            let remote = RemoteFile::from_response(&first, content_range_total(&first));
+            if !self.expected.matches(&remote) {
+                drop(first);
+                offset = 0;
+                first = self.fetch(Some(self.range_at(offset)), None).await?;
+                self.check_status(&first)?;
+            }
+        }
+
+        if first.status() != http::StatusCode::PARTIAL_CONTENT {
+            let size = first
+                .headers()
+                .get(http::header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok()?.parse().ok());
+            self.found(&RemoteFile::from_response(&first, size));
+            let file = self.open(0).await?;
+            let len = self
+                .write_body(&file, 0, None, first.into_body(), |written| {
+                    self.report(written)
+                })
+                .await?;
+            self.report(len);
+            return Ok(len);
+        }
+
+        let file = self.open(offset).await?;
+        let total = content_range_total(&first).ok_or(DownloadError::InvalidContentRange)?;
+        let remote = RemoteFile::from_response(&first, Some(total));
+        self.found(&remote);
+        let if_range = remote.validator();
+        let first_range = offset..offset.saturating_add(self.chunk_size).min(total);
+        self.write_chunk(&file, first_range.clone(), first).await?;
+        self.report(first_range.end);
+
+        let ranges = (first_range.end..total)
+            .step_by(usize::try_from(self.chunk_size).unwrap_or(usize::MAX))
+            .map(|start| start..start.saturating_add(self.chunk_size).min(total));
+        let mut chunks = futures_util::stream::iter(ranges)
+            .map(|range| {
+                let file = &file;
+                async move {
+                    let response = self.fetch(Some(range.clone()), if_range).await?;
+
+                    // A full response means the file changed since the first chunk.
+                    if response.status() != http::StatusCode::PARTIAL_CONTENT {
+                        return Err(Error::InvalidStatus {
+                            url: self.url.clone(),
+                            status: response.status(),
+                        });
+                    }
+                    self.write_chunk(file, range.clone(), response).await?;
+                    Ok::<_, Error>(range)
+                }
+            })
+            .buffer_unordered(self.connections);
+
+        // Chunks complete out of order, so track the end of the written prefix.
+        let mut written = first_range.end;
+        let mut completed = BTreeMap::new();
+        while let Some(result) = chunks.next().await {
+            let range = result?;
+            completed.insert(range.start, range.end);
+            while let Some(end) = completed.remove(&written) {
+                written = end;
+            }
+            self.report(written);
+        }
+
+        Ok(total)
+    }
+
+    /// Get the range of the chunk starting at an offset.
+    fn range_at(&self, offset: u64) -> Range<u64> {
+        offset..offset.saturating_add(self.chunk_size)
+    }
+
+    /// Call the remote file function, if there is one.
+    fn found(&self, remote: &RemoteFile) {
+        if let Some(on_remote_file) = self.on_remote_file.as_ref() {
+            on_remote_file(remote);
+        }
+    }
+
+    /// Fail unless a response has the whole file or a range of it.
+    fn check_status(&self, response: &StreamingResponse) -> Result<(), Error> {
+        match response.status() {
+            http::StatusCode::OK | http::StatusCode::PARTIAL_CONTENT => Ok(()),
+            status => Err(Error::InvalidStatus {
+                url: self.url.clone(),
+                status,
+            }),
+        }
+    }
+
+    /// Call the progress function, if there is one.
+    fn report(&self, written: u64) {
+        if let Some(progress) = self.progress.as_ref() {
+            progress(written);
+        }
+    }
+
+    /// Fetch the file, or a range of it.
+    ///
+    /// If `if_range` is given, the server sends the whole file instead if it does not match.
+    /// This fails on error status codes, except for unsatisfiable ranges.
+    async fn fetch(
+        &self,
+        range: Option<Range<u64>>,
+        if_range: Option<&str>,
+    ) -> Result<StreamingResponse, Error> {
+        let has_range = range.is_some();
+        let mut request = request::file(&self.url, range)?;
+        if let Some(if_range) = if_range.filter(|_| has_range) {
+            request.headers_mut().insert(
+                http::header::IF_RANGE,
+                http::HeaderValue::from_str(if_range).map_err(http::Error::from)?,
+            );
+        }
+        let (url, response) = self.client.send_streaming(request).await?;
+        let status = response.status();
+        let unsatisfiable = has_range && status == http::StatusCode::RANGE_NOT_SATISFIABLE;
+        if (status.is_client_error() || status.is_server_error()) && !unsatisfiable {
+            return Err(Error::InvalidStatus { url, status });
+        }
+        Ok(response)
+    }
+
+    /// Open the file for writing, discarding anything past `len`.
+    async fn open(&self, len: u64) -> Result<Arc<Mutex<File>>, Error> {
+        let path = self.path.clone();
+        let file = self
+            .client
+            .spawn_blocking(move || {
+                let file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(path)?;
+                file.set_len(len)?;
+                Ok::<_, std::io::Error>(file)
+            })
+            .await?
+            .map_err(DownloadError::from)?;
+        Ok(Arc::new(Mutex::new(file)))
+    }
+
+    /// Write a chunk, checking that it has the requested size.
+    async fn write_chunk(
+        &self,
+        file: &Arc<Mutex<File>>,
+        range: Range<u64>,
+        response: StreamingResponse,
+    ) -> Result<(), Error> {
+        let expected = range.end - range.start;
+        let received = self
+            .write_body(
+                file,
+                range.start,
+                Some(expected),
+                response.into_body(),
+                |_| {},
+            )
+            .await?;
+        if received != expected {
+            return Err(DownloadError::InvalidChunk {
+                offset: range.start,
+                expected,
+                received,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Write a body to the file at an offset as it arrives, returning the number of bytes written.
+    ///
+    /// `on_write` is called with the end of the written bytes after every write.
+    /// This fails as soon as the body is longer than `max_len`.
+    async fn write_body<F>(
+        &self,
+        file: &Arc<Mutex<File>>,
+        start: u64,
+        max_len: Option<u64>,
+        mut body: BodyStream,
+        on_write: F,
+    ) -> Result<u64, Error>
+    where
+        F: Fn(u64),
+    {
+        let mut buffer = Vec::with_capacity(WRITE_BUFFER_SIZE);
+        let mut buffer_start = start;
+        let mut received = 0;
+        while let Some(piece) = body.next().await {
+            let piece = piece?;
+            received += piece.len() as u64;
+            if let Some(expected) = max_len.filter(|expected| received > *expected) {
+                return Err(DownloadError::InvalidChunk {
+                    offset: start,
+                    expected,
+                    received,
+                }
+                .into());
+            }
+            if let Some(rate_limiter) = self.client.rate_limiter() {
+                let wait = rate_limiter.reserve(piece.len() as u64);
+                if !wait.is_zero() {
+                    self.client.sleep(wait).await;
+                }
+            }
+
+            buffer.extend_from_slice(&piece);
+            if buffer.len() >= WRITE_BUFFER_SIZE {
+                let len = buffer.len() as u64;
+                let full = std::mem::replace(&mut buffer, Vec::with_capacity(WRITE_BUFFER_SIZE));
+                write_at(&self.client, file, buffer_start, full).await?;
+                buffer_start += len;
+                on_write(buffer_start);
+            }
+        }
+        if !buffer.is_empty() {
+            let len = buffer.len() as u64;
+            write_at(&self.client, file, buffer_start, buffer).await?;
+            buffer_start += len;
+            on_write(buffer_start);
+        }
+
+        // Whole bodies may be shorter than the file was before.
+        if max_len.is_none() {
+            file.lock()
+                .unwrap_or_else(|error| error.into_inner())
+                .set_len(received)
+                .map_err(DownloadError::from)?;
+        }
+
+        Ok(received)
+    }
+}
+
+/// Get the total size from the `Content-Range` header of a partial response.
+fn content_range_total(response: &StreamingResponse) -> Option<u64> {
+    response
+        .headers()
+        .get(http::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split_once('/')?
+        .1
+        .parse()
+        .ok()
+}
+
+/// Get the total size from the `Content-Range: bytes */{total}` header of a 416 response.
+fn unsatisfied_range_total(response: &StreamingResponse) -> Option<u64> {
+    response
+        .headers()
+        .get(http::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes */")?
+        .parse()
+        .ok()
+}
+
+/// Write bytes to a file at an offset.
+async fn write_at(
+    client: &Client,
+    file: &Arc<Mutex<File>>,
+    offset: u64,
+    body: Vec<u8>,
+) -> Result<(), Error> {
+    let file = file.clone();
+    client
+        .spawn_blocking(move || {
+            let mut file = file.lock().unwrap_or_else(|error| error.into_inner());
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&body)
+        })
+        .await?
+        .map_err(DownloadError::from)?;
+    Ok(())
+}
+
+/// What a downloaded file is expected to look like
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpectedFile {
+    /// The md5 hash, as hex, from the itch.io api
+    pub md5_hash: Option<String>,
+
+    /// The exact size, from the itch.io api
+    pub size: Option<u64>,
+
+    /// The approximate size, scraped from a game or download page
+    pub scraped_size: Option<ByteSize>,
+}
+
+/// The result of verifying a downloaded file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "result", rename_all = "snake_case")
+)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub enum Verification {
+    /// The md5 hash matched
+    Verified,
+
+    /// The md5 hash did not match
+    HashMismatch {
+        /// The expected hash
+        expected: String,
+
+        /// The hash of the file
+        actual: String,
+    },
+
+    /// There was no hash, but the exact size matched
+    SizeMatched,
+
+    /// The exact size did not match
+    SizeMismatch {
+        /// The expected size
+        expected: u64,
+
+        /// The size of the file
+        actual: u64,
+    },
+
+    /// There was no hash or exact size, but the size is within the bounds of the scraped size
+    SizePlausible,
+
+    /// The size is outside the bounds of the scraped size
+    SizeImplausible {
+        /// The scraped size
+        expected: ByteSize,
+
+        /// The size of the file
+        actual: u64,
+    },
+
+    /// There was nothing to verify against
+    Unverified,
+}
+
+impl Verification {
+    /// Returns true if nothing was found wrong with the file.
+    ///
+    /// This is also true for [`Verification::Unverified`].
+    pub fn is_ok(&self) -> bool {
+        matches!(
+            self,
+            Self::Verified | Self::SizeMatched | Self::SizePlausible | Self::Unverified
+        )
+    }
+}
+
+/// Verify a downloaded file.
+///
+/// Sizes are checked first, so truncated files are caught without hashing them.
+/// The scraped size is only used if there is no hash and no exact size.
+pub fn verify_file<P>(path: P, expected: &ExpectedFile) -> Result<Verification, std::io::Error>
+where
+    P: AsRef<Path>,
+{
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    verify(size, || hash_reader::<Md5, _>(&mut file), expected)
+}
+
+/// Hash a file with md5, returning the hash as lowercase hex.
+pub fn md5_file<P>(path: P) -> Result<String, std::io::Error>
+where
+    P: AsRef<Path>,
+{
+    hash_reader::<Md5, _>(&mut File::open(path)?)
+}
+
+/// Hash a file with sha256, returning the hash as lowercase hex.
+pub fn sha256_file<P>(path: P) -> Result<String, std::io::Error>
+where
+    P: AsRef<Path>,
+{
+    hash_reader::<Sha256, _>(&mut File::open(path)?)
+}
+
+/// Hash a file with md5 and sha256 in one pass, returning both as lowercase hex.
+#[cfg(feature = "serde")]
+pub(crate) fn md5_and_sha256_file<P>(path: P) -> Result<(String, String), std::io::Error>
+where
+    P: AsRef<Path>,
+{
+    let mut md5 = Md5::new();
+    let mut sha256 = Sha256::new();
+    read_pieces(&mut File::open(path)?, |piece| {
+        md5.update(piece);
+        sha256.update(piece);
+    })?;
+    Ok((to_hex(&md5.finalize()), to_hex(&sha256.finalize())))
+}
+
+/// Verify a file of the given size, hashing it with `hash` only if needed.
+pub(crate) fn verify<F>(
+    size: u64,
+    hash: F,
+    expected: &ExpectedFile,
+) -> Result<Verification, std::io::Error>
+where
+    F: FnOnce() -> Result<String, std::io::Error>,
+{
+    if let Some(expected_size) = expected.size {
+        if expected_size != size {
+            return Ok(Verification::SizeMismatch {
+                expected: expected_size,
+                actual: size,
+            });
+        }
+    }
+
+    if let Some(md5_hash) = expected.md5_hash.as_ref() {
+        let hash = hash()?;
+        if !hash.eq_ignore_ascii_case(md5_hash) {
+            return Ok(Verification::HashMismatch {
+                expected: md5_hash.clone(),
+                actual: hash,
+            });
+        }
+        return Ok(Verification::Verified);
+    }
+
+    if expected.size.is_some() {
+        return Ok(Verification::SizeMatched);
+    }
+
+    match expected.scraped_size {
+        Some(scraped_size) if scraped_size.contains(size) => Ok(Verification::SizePlausible),
+        Some(scraped_size) => Ok(Verification::SizeImplausible {
+            expected: scraped_size,
+            actual: size,
+        }),
+        None => Ok(Verification::Unverified),
+    }
+}
+
+/// Hash everything read from a reader, returning the hash as lowercase hex.
+fn hash_reader<D, R>(reader: &mut R) -> Result<String, std::io::Error>
+where
+    D: Digest,
+    R: Read,
+{
+    let mut hasher = D::new();
+    read_pieces(reader, |piece| hasher.update(piece))?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Read everything from a reader, passing each piece to `f`.
+fn read_pieces<R, F>(reader: &mut R, mut f: F) -> Result<(), std::io::Error>
+where
+    R: Read,
+    F: FnMut(&[u8]),
+{
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(());
+        }
+        f(&buffer[..n]);
+    }
+}
+
+/// Format bytes as lowercase hex.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::BoxFuture;
+    use crate::transport::Request;
+    use crate::transport::Response;
+    use crate::transport::Transport;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    /// A transport that serves a file, with support for ranges
+    #[derive(Debug)]
+    struct FileTransport {
+        data: Vec<u8>,
+        requests: AtomicUsize,
+    }
+
+    impl Transport for FileTransport {
+        fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let range = request
+                .headers()
+                .get(http::header::RANGE)
+                .and_then(|value| value.to_str().ok()?.strip_prefix("bytes=")?.split_once('-'))
+                .map(|(start, end)| {
+                    start.parse::<usize>().expect("invalid range start")
+                        ..end.parse::<usize>().expect("invalid range end") + 1
+                });
+            let response = match range {
+                Some(range) if range.start >= self.data.len() => http::Response::builder()
+                    .status(http::StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(
+                        http::header::CONTENT_RANGE,
+                        format!("bytes */{}", self.data.len()),
+                    )
+                    .body(Vec::new()),
+                Some(range) => {
+                    let end = range.end.min(self.data.len());
+                    http::Response::builder()
+                        .status(http::StatusCode::PARTIAL_CONTENT)
+                        .header(
+                            http::header::CONTENT_RANGE,
+                            format!("bytes {}-{}/{}", range.start, end - 1, self.data.len()),
+                        )
+                        .body(self.data[range.start..end].to_vec())
+                }
+                None => http::Response::builder().body(self.data.clone()),
+            };
+            Box::pin(async move { Ok(response?) })
+        }
+
+        // Bodies arrive in small pieces, like they would from a socket.
+        fn send_streaming(
+            &self,
+            request: Request,
+        ) -> BoxFuture<'_, Result<StreamingResponse, Error>> {
+            Box::pin(async move {
+                let response = self.send(request).await?;
+                Ok(response.map(|body| {
+                    let pieces: Vec<_> = body.chunks(3).map(|piece| Ok(piece.to_vec())).collect();
+                    futures_util::stream::iter(pieces).boxed()
+                }))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn chunked() {
+        let data: Vec<u8> = (0..100).collect();
+        let transport = Arc::new(FileTransport {
+            data: data.clone(),
+            requests: AtomicUsize::new(0),
+        });
+        let client = Client::with_transport(transport.clone());
+        let path =
+            std::env::temp_dir().join(format!("itch-io-download-test-{}", std::process::id()));
+        let url = Url::parse("https://files.example.com/game.zip").expect("failed to parse url");
+
+        let size = client
+            .download(&url, &path)
+            .with_chunk_size(16)
+            .with_connections(3)
+            .run()
+            .await
+            .expect("failed to download");
+        assert_eq!(size, 100);
+        assert_eq!(transport.requests.load(Ordering::SeqCst), 7);
+        assert_eq!(std::fs::read(&path).expect("failed to read file"), data);
+
+        // Resuming discards anything past the offset.
+        std::fs::write(&path, &data[..20]).expect("failed to write file");
+        let progress = Arc::new(AtomicUsize::new(0));
+        let task_progress = progress.clone();
+        client
+            .download(&url, &path)
+            .with_chunk_size(16)
+            .resume_from(16)
+            .with_progress(Arc::new(move |offset| {
+                task_progress.store(offset as usize, Ordering::SeqCst)
+            }))
+            .run()
+            .await
+            .expect("failed to resume download");
+        assert_eq!(progress.load(Ordering::SeqCst), 100);
+        assert_eq!(std::fs::read(&path).expect("failed to read file"), data);
+
+        // Resuming a complete file only costs one request.
+        let requests = transport.requests.load(Ordering::SeqCst);
+        let size = client
+            .download(&url, &path)
+            .resume_from(100)
+            .run()
+            .await
+            .expect("failed to resume complete download");
+        assert_eq!(size, 100);
+        assert_eq!(transport.requests.load(Ordering::SeqCst), requests + 1);
+        assert_eq!(std::fs::read(&path).expect("failed to read file"), data);
+
+        // Restarting does not wait on the permit held by the unsatisfiable response.
+        let mut limited_client = Client::with_transport(transport.clone());
+        limited_client.set_max_concurrent_requests(1);
+        let size = tokio::time::timeout(
+            Duration::from_secs(5),
+            limited_client.download(&url, &path).resume_from(150).run(),
+        )
+        .await
+        .expect("download deadlocked")
+        .expect("failed to restart download");
+        assert_eq!(size, 100);
+        assert_eq!(std::fs::read(&path).expect("failed to read file"), data);
+
+        let download = client.download(&url, &path);
+        download.cancel_handle().cancel();
+        assert!(matches!(
+            download.run().await,
+            Err(Error::Download(DownloadError::Cancelled))
+        ));
+
+        std::fs::remove_file(&path).expect("failed to remove file");
+    }
+
+    #[test]
+    fn verify() {
+        let path = std::env::temp_dir().join(format!("itch-io-verify-test-{}", std::process::id()));
+        std::fs::write(&path, "abc").expect("failed to write file");
+
+        let mut expected = ExpectedFile {
+            md5_hash: Some("900150983cd24fb0d6963f7d28e17f72".into()),
+            size: Some(3),
+            scraped_size: None,
+        };
+        assert_eq!(
+            verify_file(&path, &expected).expect("failed to verify file"),
+            Verification::Verified
+        );
+
+        expected.size = Some(4);
+        assert_eq!(
+            verify_file(&path, &expected).expect("failed to verify file"),
+            Verification::SizeMismatch {
+                expected: 4,
+                actual: 3
+            }
+        );
+
+        let expected = ExpectedFile {
+            scraped_size: Some("1 kB".parse().expect("failed to parse size")),
+            ..ExpectedFile::default()
+        };
+        assert!(!verify_file(&path, &expected)
+            .expect("failed to verify file")
+            .is_ok());
+
+        std::fs::remove_file(&path).expect("failed to remove file");
+    }
+}
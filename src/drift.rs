@@ -0,0 +1,380 @@
+//! Detecting parser drift.
+//!
+//! itch.io changes its markup from time to time, which silently breaks the parsers.
+//! This runs every parser over a set of pages and reports how often each field was extracted,
+//! along with values the parsers do not know about, like unseen platform icon classes.
+//! Reports can be saved and compared against later runs to find regressions.
+
+use crate::types::download_info::LightboxMessageKind;
+use crate::types::Diagnostic;
+use crate::types::Parsed;
+use crate::types::Platform;
+use crate::types::Selectors;
+use crate::types::Upload;
+use crate::DownloadPage;
+use crate::GamePage;
+use crate::Lightbox;
+use scraper::Html;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Upload fields checked for every upload row
+const UPLOAD_FIELDS: &[&str] = &["title", "size", "id", "platforms"];
+
+/// An error that may occur while parsing a page kind
+#[derive(Debug, thiserror::Error)]
+#[error("unknown page kind `{0}`")]
+pub struct ParsePageKindError(pub String);
+
+/// A kind of page with a parser.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum PageKind {
+    /// A game page
+    GamePage,
+
+    /// A download page
+    DownloadPage,
+
+    /// The lightbox shown after clicking download
+    Lightbox,
+}
+
+impl PageKind {
+    /// All page kinds
+    pub const ALL: &'static [Self] = &[Self::GamePage, Self::DownloadPage, Self::Lightbox];
+
+    /// Get this as a str.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::GamePage => "game_page",
+            Self::DownloadPage => "download_page",
+            Self::Lightbox => "lightbox",
+        }
+    }
+
+    /// Returns true if pages of this kind list uploads.
+    fn has_uploads(self) -> bool {
+        matches!(self, Self::GamePage | Self::DownloadPage)
+    }
+
+    /// The fields checked once per page, not counting uploads.
+    fn fields(self) -> &'static [&'static str] {
+        match self {
+            Self::GamePage => &["title", "twitter_url", "id", "csrf_token", "view_html_url"],
+            Self::DownloadPage => &[],
+            Self::Lightbox => &["thank_you"],
+        }
+    }
+}
+
+impl FromStr for PageKind {
+    type Err = ParsePageKindError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.as_str() == input)
+            .ok_or_else(|| ParsePageKindError(input.into()))
+    }
+}
+
+impl Display for PageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Extraction stats for a field
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldStats {
+    /// The number of times the field was looked for
+    pub attempts: u64,
+
+    /// The number of times the field failed to parse
+    pub failures: u64,
+}
+
+impl FieldStats {
+    /// Get the fraction of attempts that succeeded, from 0 to 1.
+    ///
+    /// This is 1 if the field was never looked for.
+    pub fn success_rate(&self) -> f64 {
+        if self.attempts == 0 {
+            return 1.0;
+        }
+
+        (self.attempts - self.failures) as f64 / self.attempts as f64
+    }
+}
+
+/// A report of how well the parsers did over a set of pages
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DriftReport {
+    /// The number of pages checked, by kind
+    pub pages: BTreeMap<PageKind, u64>,
+
+    /// Stats for each field, like `game_page.title` or `download_page.uploads[].size`
+    pub fields: BTreeMap<String, FieldStats>,
+
+    /// Platform icon classes that were not recognized
+    pub unknown_platforms: BTreeSet<String>,
+}
+
+impl DriftReport {
+    /// Make a new, empty report.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a page and add the results to this report.
+    pub fn check_page(&mut self, kind: PageKind, html: &Html, selectors: &Selectors) {
+        match kind {
+            PageKind::GamePage => {
+                let Parsed { value, warnings } = GamePage::from_html_lenient_with(html, selectors);
+                self.record(kind, &warnings, &value.uploads);
+            }
+            PageKind::DownloadPage => {
+                let Parsed { value, warnings } =
+                    DownloadPage::from_html_lenient_with(html, selectors);
+                self.record(kind, &warnings, &value.uploads);
+            }
+            PageKind::Lightbox => {
+                // Every lightbox thanks the player, so a missing thank-you means the selectors broke.
+                let lightbox = Lightbox::from_html_with(html, selectors);
+                let mut warnings = Vec::new();
+                if lightbox
+                    .messages_of(LightboxMessageKind::ThankYou)
+                    .next()
+                    .is_none()
+                {
+                    warnings.push(Diagnostic::new("thank_you", "missing thank-you message"));
+                }
+                self.record(kind, &warnings, &[]);
+            }
+        }
+    }
+
+    /// Add the results of another report to this one.
+    pub fn merge(&mut self, other: DriftReport) {
+        for (kind, pages) in other.pages {
+            *self.pages.entry(kind).or_default() += pages;
+        }
+        for (field, stats) in other.fields {
+            let entry = self.fields.entry(field).or_default();
+            entry.attempts += stats.attempts;
+            entry.failures += stats.failures;
+        }
+        self.unknown_platforms.extend(other.unknown_platforms);
+    }
+
+    /// Find regressions.
+    ///
+    /// Without a baseline, every failed field and every unknown platform is a regression.
+    /// With a baseline, only fields that got worse and platforms that are new are.
+    pub fn regressions(&self, baseline: Option<&DriftReport>) -> Vec<Regression> {
+        let mut regressions = Vec::new();
+        for (field, stats) in self.fields.iter() {
+            let baseline_rate = baseline
+                .and_then(|baseline| baseline.fields.get(field))
+                .map_or(1.0, FieldStats::success_rate);
+            let success_rate = stats.success_rate();
+            if success_rate < baseline_rate {
+                regressions.push(Regression::FieldSuccessRate {
+                    field: field.clone(),
+                    success_rate,
+                    baseline: baseline_rate,
+                });
+            }
+        }
+
+        for platform in self.unknown_platforms.iter() {
+            let known =
+                baseline.is_some_and(|baseline| baseline.unknown_platforms.contains(platform));
+            if !known {
+                regressions.push(Regression::UnknownPlatform(platform.clone()));
+            }
+        }
+
+        regressions
+    }
+
+    /// Record the results of parsing a page.
+    fn record<E>(&mut self, kind: PageKind, warnings: &[Diagnostic<E>], uploads: &[Upload]) {
+        *self.pages.entry(kind).or_default() += 1;
+
+        for field in kind.fields() {
+            self.field(kind, field).attempts += 1;
+        }
+
+        if kind.has_uploads() {
+            let failed_uploads = warnings
+                .iter()
+                .filter(|warning| warning.field.starts_with("uploads["))
+                .count();
+            let upload_rows = (uploads.len() + failed_uploads) as u64;
+            for field in UPLOAD_FIELDS {
+                self.field(kind, &format!("uploads[].{field}")).attempts += upload_rows;
+            }
+        }
+
+        for warning in warnings {
+            let field = normalize_field(&warning.field);
+            let stats = self.field(kind, &field);
+            // Fields that are not checked on every page only count when they fail.
+            if !kind.fields().contains(&field.as_str()) && !field.starts_with("uploads[].") {
+                stats.attempts += 1;
+            }
+            stats.failures += 1;
+        }
+
+        for upload in uploads {
+            for platform in upload.platforms.iter() {
+                if let Platform::Other(class) = platform {
+                    self.unknown_platforms.insert(class.clone());
+                }
+            }
+        }
+    }
+
+    /// Get the stats of a field, inserting them if they do not exist.
+    fn field(&mut self, kind: PageKind, field: &str) -> &mut FieldStats {
+        self.fields.entry(format!("{kind}.{field}")).or_default()
+    }
+}
+
+/// Parse a set of pages and report how well the parsers did.
+pub fn check_pages<'a, I>(pages: I, selectors: &Selectors) -> DriftReport
+where
+    I: IntoIterator<Item = (PageKind, &'a Html)>,
+{
+    let mut report = DriftReport::new();
+    for (kind, html) in pages {
+        report.check_page(kind, html, selectors);
+    }
+    report
+}
+
+/// A regression found by comparing a report against a baseline
+#[derive(Debug, Clone, PartialEq)]
+pub enum Regression {
+    /// A field was extracted less often than before
+    FieldSuccessRate {
+        /// The field
+        field: String,
+
+        /// The success rate
+        success_rate: f64,
+
+        /// The success rate of the baseline
+        baseline: f64,
+    },
+
+    /// A platform icon class that was not seen before
+    UnknownPlatform(String),
+}
+
+impl Display for Regression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FieldSuccessRate {
+                field,
+                success_rate,
+                baseline,
+            } => write!(
+                f,
+                "`{field}` was extracted {:.1}% of the time, down from {:.1}%",
+                success_rate * 100.0,
+                baseline * 100.0
+            ),
+            Self::UnknownPlatform(platform) => write!(f, "unknown platform `{platform}`"),
+        }
+    }
+}
+
+/// Replace upload indexes in a field name, so that all rows share stats.
+fn normalize_field(field: &str) -> String {
+    match field
+        .strip_prefix("uploads[")
+        .and_then(|rest| rest.split_once(']'))
+    {
+        Some((_, rest)) => format!("uploads[]{rest}"),
+        None => field.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let html = Html::parse_document(
+            r#"<html>
+<head>
+    <meta name="twitter:url" content="https://tumblewed.itch.io/doghouse-2">
+    <meta name="csrf_token" value="token">
+</head>
+<body>
+    <h1 class="game_title">Doghouse 2</h1>
+    <div class="upload">
+        <strong class="name">doghouse.zip</strong>
+        <span class="file_size"><span>5 MB</span></span>
+        <span class="download_platforms"><span class="icon icon-psp"></span></span>
+    </div>
+    <div class="upload">
+        <strong class="name">broken.zip</strong>
+        <span class="file_size"><span>lots</span></span>
+        <span class="download_platforms"></span>
+    </div>
+</body>
+</html>"#,
+        );
+        let report = check_pages([(PageKind::GamePage, &html)], &Selectors::default());
+
+        assert_eq!(report.pages[&PageKind::GamePage], 1);
+        assert_eq!(report.fields["game_page.title"].success_rate(), 1.0);
+        assert_eq!(
+            report.fields["game_page.uploads[].size"],
+            FieldStats {
+                attempts: 2,
+                failures: 1
+            }
+        );
+        assert!(report.unknown_platforms.contains("psp"));
+
+        let regressions = report.regressions(None);
+        assert_eq!(regressions.len(), 2);
+        assert!(report.regressions(Some(&report)).is_empty());
+    }
+
+    #[test]
+    fn lightbox() {
+        let good = Html::parse_fragment("<h2>Thanks for downloading!</h2>");
+        let broken = Html::parse_fragment("<h3>Thanks for downloading!</h3>");
+        let report = check_pages(
+            [(PageKind::Lightbox, &good), (PageKind::Lightbox, &broken)],
+            &Selectors::default(),
+        );
+
+        assert_eq!(report.pages[&PageKind::Lightbox], 2);
+        assert_eq!(
+            report.fields["lightbox.thank_you"],
+            FieldStats {
+                attempts: 2,
+                failures: 1
+            }
+        );
+        assert!(!report.fields.contains_key("lightbox.uploads[].size"));
+    }
+}
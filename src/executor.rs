@@ -0,0 +1,78 @@
+//! Executors, for running blocking work like parsing pages.
+//!
+//! Parsing a page builds the whole DOM, which can take a while for large pages,
+//! so the client moves it off the async executor.
+//! Executors also provide the timer used for download rate limits and retry backoff.
+//! Implement [`Executor`] to use a runtime other than tokio, like smol's `blocking::unblock`:
+//!
+//! ```ignore
+//! #[derive(Debug)]
+//! struct SmolExecutor;
+//!
+//! impl Executor for SmolExecutor {
+//!     fn spawn_blocking(&self, task: BlockingTask) -> BoxFuture<'static, Result<(), ExecutorError>> {
+//!         Box::pin(async move {
+//!             blocking::unblock(task).await;
+//!             Ok(())
+//!         })
+//!     }
+//! }
+//! ```
+
+use crate::transport::BoxFuture;
+use std::time::Duration;
+
+/// A blocking task
+pub type BlockingTask = Box<dyn FnOnce() + Send + 'static>;
+
+/// An error that may occur while running a blocking task, like a panic
+pub type ExecutorError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// A way to run blocking tasks
+pub trait Executor: std::fmt::Debug + Send + Sync {
+    /// Run a blocking task, resolving when it is done.
+    fn spawn_blocking(&self, task: BlockingTask) -> BoxFuture<'static, Result<(), ExecutorError>>;
+
+    /// Wait for a duration.
+    ///
+    /// By default this sleeps in a blocking task, which works with any runtime.
+    /// Runtimes with a timer should override it.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let task = self.spawn_blocking(Box::new(move || std::thread::sleep(duration)));
+        Box::pin(async move {
+            let _ = task.await;
+        })
+    }
+}
+
+/// An executor using tokio's blocking thread pool.
+///
+/// This must be used from inside a tokio runtime.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TokioExecutor;
+
+impl Executor for TokioExecutor {
+    fn spawn_blocking(&self, task: BlockingTask) -> BoxFuture<'static, Result<(), ExecutorError>> {
+        Box::pin(async move {
+            tokio::task::spawn_blocking(task).await?;
+            Ok(())
+        })
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// An executor that runs tasks on the current thread, blocking the async executor.
+///
+/// This works with any runtime, and is fine for small pages or single threaded programs.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct InlineExecutor;
+
+impl Executor for InlineExecutor {
+    fn spawn_blocking(&self, task: BlockingTask) -> BoxFuture<'static, Result<(), ExecutorError>> {
+        task();
+        Box::pin(std::future::ready(Ok(())))
+    }
+}
@@ -0,0 +1,751 @@
+//! Downloading every upload of a game.
+//!
+//! Start a game download with [`Client::download_game`](crate::Client::download_game).
+//! The uploads are written into one directory, next to a [`Manifest`] in `manifest.json`.
+
+use crate::download::md5_and_sha256_file;
+use crate::download::verify;
+use crate::download::DownloadError;
+use crate::download::ExpectedFile;
+use crate::download::Verification;
+use crate::schema::Versioned;
+use crate::store::Store;
+use crate::time::unix_time;
+use crate::ByteSize;
+use crate::Client;
+use crate::Error;
+use crate::ExternalDownload;
+use crate::GamePage;
+use crate::GameUrl;
+use crate::Platform;
+use crate::Upload;
+use crate::UploadId;
+use crate::UploadTag;
+use percent_encoding::percent_decode_str;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use url::Url;
+
+/// The file name of the manifest
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// A record of a game download
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Manifest {
+    /// The game page
+    pub game: GamePage,
+
+    /// The selected uploads
+    pub uploads: Vec<ManifestUpload>,
+}
+
+impl Manifest {
+    /// Get the record of an earlier download of an upload with the given md5 hash.
+    ///
+    /// Only verified downloads with both hashes are returned.
+    fn download_with_hash(&self, id: UploadId, md5_hash: &str) -> Option<&ManifestUpload> {
+        self.verified_download(id).filter(|entry| {
+            entry
+                .md5_hash
+                .as_ref()
+                .is_some_and(|entry_hash| entry_hash.eq_ignore_ascii_case(md5_hash))
+        })
+    }
+
+    /// Get the record of an earlier download of an upload, if the upload looks unchanged since.
+    ///
+    /// The upload must have the same id, and the same size, date and version on the game page.
+    /// Only verified downloads with both hashes are returned.
+    fn unchanged_download(&self, upload: &Upload, id: UploadId) -> Option<&ManifestUpload> {
+        let entry = self.verified_download(id)?;
+
+        // Uploads of games that are not free have no ids on the game page, so those are matched by title.
+        self.game.uploads.iter().find(|other| {
+            other
+                .id
+                .map_or(other.title == entry.title, |other_id| other_id == id)
+                && other.size == upload.size
+                && other.upload_date == upload.upload_date
+                && other.version == upload.version
+        })?;
+
+        Some(entry)
+    }
+
+    /// Get the record of an earlier verified download of an upload with both hashes.
+    fn verified_download(&self, id: UploadId) -> Option<&ManifestUpload> {
+        self.uploads.iter().find(|entry| {
+            entry.id == Some(id)
+                && entry.md5_hash.is_some()
+                && entry.sha256_hash.is_some()
+                && entry.verification.as_ref().is_some_and(Verification::is_ok)
+        })
+    }
+
+    /// Load a manifest from a json file.
+    pub fn load<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let text = std::fs::read_to_string(path).map_err(DownloadError::from)?;
+        let manifest: Versioned<Self> = serde_json::from_str(&text)?;
+        Ok(manifest.into_inner())
+    }
+}
+
+/// A record of an upload download
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct ManifestUpload {
+    /// The upload id, if it could be found
+    pub id: Option<UploadId>,
+
+    /// The upload title
+    pub title: String,
+
+    /// The file name, relative to the manifest, if the upload was downloaded
+    pub filename: Option<String>,
+
+    /// The file size, in bytes
+    pub size: Option<u64>,
+
+    /// The md5 hash of the file, as lowercase hex
+    pub md5_hash: Option<String>,
+
+    /// The sha256 hash of the file, as lowercase hex.
+    ///
+    /// In a store, this names the blob of the file.
+    #[serde(default)]
+    pub sha256_hash: Option<String>,
+
+    /// The result of verifying the file
+    pub verification: Option<Verification>,
+
+    /// When the download finished, in seconds since the unix epoch
+    pub downloaded_at: Option<u64>,
+
+    /// The target of an external upload, which is not downloaded
+    pub external: Option<ExternalDownload>,
+
+    /// Why the upload could not be downloaded
+    pub error: Option<String>,
+}
+
+impl ManifestUpload {
+    /// Make a record for an upload that has not been downloaded yet.
+    fn new(id: Option<UploadId>, title: &str) -> Self {
+        Self {
+            id,
+            title: title.into(),
+            filename: None,
+            size: None,
+            md5_hash: None,
+            sha256_hash: None,
+            verification: None,
+            downloaded_at: None,
+            external: None,
+            error: None,
+        }
+    }
+}
+
+/// A download of every upload of a game.
+///
+/// Call [`GameDownload::run`] to start it.
+#[derive(Debug)]
+pub struct GameDownload {
+    /// The client requests are sent with
+    client: Client,
+
+    /// The game url
+    url: GameUrl,
+
+    /// The directory to write to
+    dir: PathBuf,
+
+    /// Keep uploads for any of these platforms, or all uploads if empty
+    platforms: Vec<Platform>,
+
+    /// Keep uploads with any of these tags, or all uploads if empty
+    tags: Vec<UploadTag>,
+
+    /// Skip uploads with any of these tags
+    excluded_tags: Vec<UploadTag>,
+
+    /// The max number of chunks in flight for a download
+    connections: usize,
+
+    /// The store downloaded files are moved into
+    store: Option<Store>,
+}
+
+impl GameDownload {
+    /// Make a new game download.
+    pub(crate) fn new(client: Client, url: GameUrl, dir: PathBuf) -> Self {
+        Self {
+            client,
+            url,
+            dir,
+            platforms: Vec::new(),
+            tags: Vec::new(),
+            excluded_tags: Vec::new(),
+            connections: 1,
+            store: None,
+        }
+    }
+
+    /// Make a new game download into a store.
+    pub(crate) fn new_in_store(client: Client, url: GameUrl, store: Store) -> Self {
+        let dir = store.game_dir(&url);
+        Self {
+            store: Some(store),
+            ..Self::new(client, url, dir)
+        }
+    }
+
+    /// Only download uploads for this platform.
+    ///
+    /// This may be called more than once, to keep uploads for any of the platforms.
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        self.platforms.push(platform);
+        self
+    }
+
+    /// Only download uploads with this tag.
+    ///
+    /// This may be called more than once, to keep uploads with any of the tags.
+    pub fn with_tag(mut self, tag: UploadTag) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    /// Skip uploads with this tag.
+    pub fn without_tag(mut self, tag: UploadTag) -> Self {
+        self.excluded_tags.push(tag);
+        self
+    }
+
+    /// Set the max number of chunks in flight for a download.
+    ///
+    /// See [`Download::with_connections`](crate::download::Download::with_connections).
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.connections = connections.max(1);
+        self
+    }
+
+    /// Returns true if an upload passes the filters.
+    fn is_selected(&self, upload: &Upload) -> bool {
+        (self.platforms.is_empty()
+            || self
+                .platforms
+                .iter()
+                .any(|platform| upload.supports(platform)))
+            && (self.tags.is_empty() || self.tags.iter().any(|tag| upload.has_tag(*tag)))
+            && !self.excluded_tags.iter().any(|tag| upload.has_tag(*tag))
+    }
+
+    /// Download the selected uploads, then write the manifest.
+    ///
+    /// Uploads are downloaded one at a time.
+    /// A failed upload does not stop the others; its error is recorded in the manifest instead.
+    /// External uploads are not downloaded, but their targets are recorded.
+    pub async fn run(self) -> Result<Manifest, Error> {
+        let game_page = self.client.get_game_page(&self.url).await?;
+        let dir = self.dir.clone();
+        self.client
+            .spawn_blocking(move || std::fs::create_dir_all(dir))
+            .await?
+            .map_err(DownloadError::from)?;
+
+        let selected: Vec<&Upload> = game_page
+            .uploads
+            .iter()
+            .filter(|upload| self.is_selected(upload))
+            .collect();
+
+        // Uploads of games that are not free only have ids on the download page.
+        let download_page_uploads = if selected.iter().any(|upload| upload.id.is_none()) {
+            let download_page_url = self
+                .client
+                .get_download_page_url_with_session(&game_page.twitter_url)
+                .await?;
+            self.client
+                .get_download_page(download_page_url.url.as_str())
+                .await?
+                .uploads
+        } else {
+            Vec::new()
+        };
+
+        // In a store, the last manifest tells which uploads are already there,
+        // even without an api key to get their hashes.
+        let previous = match self.store.as_ref() {
+            Some(_) => {
+                let path = self.dir.join(MANIFEST_FILE_NAME);
+                self.client
+                    .spawn_blocking(move || Manifest::load(path).ok())
+                    .await?
+            }
+            None => None,
+        };
+
+        let mut filenames = HashSet::new();
+        let mut uploads = Vec::with_capacity(selected.len());
+        for upload in selected {
+            let id = match upload.id {
+                Some(id) => Ok(id),
+                None => find_upload_id(upload, &download_page_uploads),
+            };
+            let mut entry = ManifestUpload::new(id.ok(), &upload.title);
+            match id {
+                Ok(id) => {
+                    let result = self
+                        .download_upload(
+                            &game_page,
+                            previous.as_ref(),
+                            upload,
+                            id,
+                            &mut filenames,
+                            &mut entry,
+                        )
+                        .await;
+                    if let Err(error) = result {
+                        entry.error = Some(error.to_string());
+                    }
+                }
+                Err(error) => {
+                    entry.error = Some(error.into());
+                }
+            }
+            uploads.push(entry);
+        }
+
+        let manifest = Manifest {
+            game: game_page,
+            uploads,
+        };
+        let json = serde_json::to_vec_pretty(&Versioned::new(&manifest))?;
+        let path = self.dir.join(MANIFEST_FILE_NAME);
+        self.client
+            .spawn_blocking(move || std::fs::write(path, json))
+            .await?
+            .map_err(DownloadError::from)?;
+
+        Ok(manifest)
+    }
+
+    /// Download an upload, filling in its manifest entry.
+    async fn download_upload(
+        &self,
+        game_page: &GamePage,
+        previous: Option<&Manifest>,
+        upload: &Upload,
+        id: UploadId,
+        filenames: &mut HashSet<String>,
+        entry: &mut ManifestUpload,
+    ) -> Result<(), Error> {
+        let upload_info = self.client.get_upload_info_if_available(id).await?;
+        let api_filename = upload_info
+            .as_ref()
+            .and_then(|upload_info| upload_info.filename.as_deref())
+            .and_then(sanitize_filename);
+        let expected = ExpectedFile {
+            md5_hash: upload_info
+                .as_ref()
+                .and_then(|upload_info| upload_info.md5_hash.clone()),
+            size: upload_info
+                .as_ref()
+                .and_then(|upload_info| upload_info.size),
+            scraped_size: upload.size,
+        };
+
+        // Uploads the store already has are linked without downloading them again.
+        // They are found in the last manifest, by the md5 hash from the api,
+        // or without one, if the upload looks unchanged on the game page.
+        let stored = previous.and_then(|previous| match expected.md5_hash.as_deref() {
+            Some(md5_hash) => previous.download_with_hash(id, md5_hash),
+            None => previous.unchanged_download(upload, id),
+        });
+        if let (Some(store), Some(stored)) = (self.store.as_ref(), stored) {
+            if stored
+                .sha256_hash
+                .as_ref()
+                .is_some_and(|sha256_hash| store.contains(sha256_hash))
+            {
+                let filename = api_filename
+                    .or_else(|| stored.filename.clone())
+                    .unwrap_or_else(|| format!("upload-{id}"));
+                let filename = unique_filename(filename, id, filenames);
+                return self
+                    .link_upload(store, stored, filename, &expected, entry)
+                    .await;
+            }
+        }
+
+        let download_info = self
+            .client
+            .get_download_info_with_session(&game_page.twitter_url, id)
+            .await?;
+        if download_info.external {
+            entry.external = download_info.external_download();
+            return Ok(());
+        }
+
+        let filename = api_filename
+            .or_else(|| filename_from_url(&download_info.url))
+            .unwrap_or_else(|| format!("upload-{id}"));
+        let filename = unique_filename(filename, id, filenames);
+
+        let path = self.dir.join(&filename);
+        if self.store.is_some() {
+            // A link left by an earlier run must not be written through, or its blob would change.
+            let path = path.clone();
+            self.client
+                .spawn_blocking(move || match std::fs::remove_file(path) {
+                    Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+                    _ => Ok(()),
+                })
+                .await?
+                .map_err(DownloadError::from)?;
+        }
+        let size = self
+            .client
+            .download(&download_info.url, &path)
+            .with_connections(self.connections)
+            .run()
+            .await?;
+        entry.filename = Some(filename);
+        entry.size = Some(size);
+        entry.downloaded_at = Some(unix_time());
+
+        let hash_path = path.clone();
+        let (md5_hash, sha256_hash) = self
+            .client
+            .spawn_blocking(move || md5_and_sha256_file(hash_path))
+            .await?
+            .map_err(DownloadError::from)?;
+        let verification =
+            verify(size, || Ok(md5_hash.clone()), &expected).map_err(DownloadError::from)?;
+
+        // Files that fail verification are kept out of the store, so the next run downloads them again.
+        if let Some(store) = self.store.clone().filter(|_| verification.is_ok()) {
+            let sha256_hash = sha256_hash.clone();
+            self.client
+                .spawn_blocking(move || {
+                    store.insert_with_hash(&path, &sha256_hash)?;
+                    store.link(&sha256_hash, &path)
+                })
+                .await??;
+        }
+        entry.verification = Some(verification);
+        entry.md5_hash = Some(md5_hash);
+        entry.sha256_hash = Some(sha256_hash);
+
+        Ok(())
+    }
+
+    /// Link an upload the store already has, filling in its manifest entry.
+    ///
+    /// `stored` is the record of the earlier download, which has both hashes.
+    async fn link_upload(
+        &self,
+        store: &Store,
+        stored: &ManifestUpload,
+        filename: String,
+        expected: &ExpectedFile,
+        entry: &mut ManifestUpload,
+    ) -> Result<(), Error> {
+        let md5_hash = stored.md5_hash.clone().unwrap_or_default();
+        let sha256_hash = stored.sha256_hash.clone().unwrap_or_default();
+        let path = self.dir.join(&filename);
+        let blob_path = store.blob_path(&sha256_hash)?;
+        let store = store.clone();
+        let link_hash = sha256_hash.clone();
+        let size = self
+            .client
+            .spawn_blocking(move || {
+                store.link(&link_hash, path)?;
+                Ok::<_, Error>(
+                    std::fs::metadata(blob_path)
+                        .map_err(DownloadError::from)?
+                        .len(),
+                )
+            })
+            .await??;
+
+        entry.filename = Some(filename);
+        entry.size = Some(size);
+        entry.downloaded_at = Some(unix_time());
+        entry.verification =
+            Some(verify(size, || Ok(md5_hash.clone()), expected).map_err(DownloadError::from)?);
+        entry.md5_hash = Some(md5_hash);
+        entry.sha256_hash = Some(sha256_hash);
+
+        Ok(())
+    }
+}
+
+/// Find the id of a game page upload on the download page.
+///
+/// Uploads must have the same title, a size within the displayed precision, and the same platforms.
+/// Returns why no id was found otherwise.
+fn find_upload_id(
+    upload: &Upload,
+    download_page_uploads: &[Upload],
+) -> Result<UploadId, &'static str> {
+    let candidates: Vec<&Upload> = download_page_uploads
+        .iter()
+        .filter(|candidate| {
+            candidate.id.is_some()
+                && candidate.title == upload.title
+                && sizes_agree(candidate.size, upload.size)
+                && candidate.platforms.len() == upload.platforms.len()
+                && candidate
+                    .platforms
+                    .iter()
+                    .all(|platform| upload.platforms.contains(platform))
+        })
+        .collect();
+    match candidates.as_slice() {
+        [candidate] => candidate.id.ok_or("missing upload id"),
+        [] => Err("no upload on the download page matches the title, size and platforms"),
+        _ => Err("ambiguous upload, several uploads on the download page match"),
+    }
+}
+
+/// Returns true if two displayed sizes could be the same file.
+///
+/// External uploads have no size, so they only agree with each other.
+fn sizes_agree(a: Option<ByteSize>, b: Option<ByteSize>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.contains(b.bytes()) || b.contains(a.bytes()),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Make a file name unique within a game download, by prefixing the upload id if it is taken.
+///
+/// If the prefixed name is taken too, a counter is added until the name is free.
+fn unique_filename(filename: String, id: UploadId, filenames: &mut HashSet<String>) -> String {
+    if filenames.insert(filename.clone()) {
+        return filename;
+    }
+    let mut candidate = format!("{id}-{filename}");
+    let mut counter = 2;
+    while !filenames.insert(candidate.clone()) {
+        candidate = format!("{id}-{counter}-{filename}");
+        counter += 1;
+    }
+    candidate
+}
+
+/// Get a file name from the last segment of a download url.
+fn filename_from_url(url: &Url) -> Option<String> {
+    let segment = url.path_segments()?.next_back()?;
+    let filename = percent_decode_str(segment).decode_utf8().ok()?;
+    sanitize_filename(&filename)
+}
+
+/// Make a file name safe to join to a directory.
+///
+/// Returns `None` if nothing usable is left.
+fn sanitize_filename(filename: &str) -> Option<String> {
+    let filename: String = filename
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '\0' => '_',
+            c => c,
+        })
+        .collect();
+    let filename = filename.trim();
+    if filename.is_empty() || filename == "." || filename == ".." || filename == MANIFEST_FILE_NAME
+    {
+        return None;
+    }
+    Some(filename.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::Cassette;
+    use crate::transport::ReplayTransport;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn run() {
+        let dir =
+            std::env::temp_dir().join(format!("itch-io-game-download-test-{}", std::process::id()));
+        let client = Client::with_transport(Arc::new(
+            ReplayTransport::load(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/test_data/cassettes/game_download.json"
+            ))
+            .expect("failed to load cassette"),
+        ));
+        let url = GameUrl::parse("https://creator.itch.io/bundle-game").expect("invalid game url");
+
+        // The linux upload and the demo are filtered out.
+        client
+            .download_game(&url, &dir)
+            .with_platform(Platform::Windows)
+            .without_tag(UploadTag::Demo)
+            .run()
+            .await
+            .expect("failed to download game");
+        let manifest =
+            Manifest::load(dir.join(MANIFEST_FILE_NAME)).expect("failed to load manifest");
+        assert_eq!(manifest.game.title, "Bundle Game");
+        let ids: Vec<_> = manifest.uploads.iter().map(|upload| upload.id).collect();
+        assert_eq!(
+            ids,
+            [
+                Some(UploadId(3001)),
+                Some(UploadId(3004)),
+                Some(UploadId(3005))
+            ]
+        );
+
+        let downloaded = &manifest.uploads[0];
+        assert_eq!(downloaded.filename.as_deref(), Some("game-windows.zip"));
+        assert_eq!(downloaded.size, Some(3));
+        assert_eq!(
+            downloaded.md5_hash.as_deref(),
+            Some("900150983cd24fb0d6963f7d28e17f72")
+        );
+        assert_eq!(downloaded.verification, Some(Verification::SizePlausible));
+        assert!(downloaded.downloaded_at.is_some());
+        assert_eq!(downloaded.error, None);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("game-windows.zip")).expect("failed to read file"),
+            "abc"
+        );
+
+        // External uploads are recorded, but not downloaded.
+        let external = &manifest.uploads[1];
+        assert_eq!(
+            external
+                .external
+                .as_ref()
+                .map(|external| external.url().as_str()),
+            Some("https://www.dropbox.com/s/abc/game-mirror.zip?dl=1")
+        );
+        assert_eq!(external.filename, None);
+
+        // A failed upload does not stop the others.
+        let failed = &manifest.uploads[2];
+        assert_eq!(failed.filename, None);
+        assert!(failed.error.is_some());
+
+        std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn filenames() {
+        let url = Url::parse("https://files.example.com/a/My%20Game%2Bv2.zip?sig=1")
+            .expect("failed to parse url");
+        assert_eq!(filename_from_url(&url).as_deref(), Some("My Game+v2.zip"));
+        assert_eq!(sanitize_filename("../evil").as_deref(), Some(".._evil"));
+        assert_eq!(sanitize_filename(".."), None);
+        assert_eq!(sanitize_filename(MANIFEST_FILE_NAME), None);
+
+        let mut filenames = HashSet::new();
+        let names: Vec<String> = ["a.zip", "a.zip", "1-a.zip", "a.zip"]
+            .into_iter()
+            .map(|name| unique_filename(name.into(), UploadId(1), &mut filenames))
+            .collect();
+        assert_eq!(names, ["a.zip", "1-a.zip", "1-1-a.zip", "1-2-a.zip"]);
+    }
+
+    #[tokio::test]
+    async fn store() {
+        let dir =
+            std::env::temp_dir().join(format!("itch-io-game-store-test-{}", std::process::id()));
+        let store = Store::open(&dir).expect("failed to open store");
+
+        // The second run gets everything again, except the file, which must come from the store.
+        let mut cassette = Cassette::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_data/cassettes/game_download.json"
+        ))
+        .expect("failed to load cassette");
+        let again: Vec<_> = cassette
+            .interactions
+            .iter()
+            .filter(|interaction| {
+                !interaction
+                    .request
+                    .uri
+                    .ends_with("/file/3001?after_download_lightbox=true")
+            })
+            .filter(|interaction| !interaction.request.uri.ends_with("/game-windows.zip"))
+            .cloned()
+            .collect();
+        cassette.interactions.extend(again);
+        let client = Client::with_transport(Arc::new(ReplayTransport::new(cassette)));
+        let url = GameUrl::parse("https://creator.itch.io/bundle-game").expect("invalid game url");
+
+        for _ in 0..2 {
+            let manifest = client
+                .download_game_to_store(&url, &store)
+                .with_platform(Platform::Windows)
+                .without_tag(UploadTag::Demo)
+                .run()
+                .await
+                .expect("failed to download game");
+            let downloaded = &manifest.uploads[0];
+            assert_eq!(downloaded.error, None);
+            assert_eq!(downloaded.filename.as_deref(), Some("game-windows.zip"));
+            assert_eq!(
+                std::fs::read_to_string(store.game_dir(&url).join("game-windows.zip"))
+                    .expect("failed to read file"),
+                "abc"
+            );
+        }
+        assert!(store.contains("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+
+        std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn upload_ids() {
+        let upload = |id: Option<u64>, size: &str, platform: &str| -> Upload {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "title": "game.zip",
+                "size": size,
+                "platforms": [platform],
+                "upload_date": null,
+                "version": null,
+                "demo": false,
+                "soundtrack": false,
+                "preorder": false,
+                "external": false,
+            }))
+            .expect("invalid upload")
+        };
+
+        let download_page = [
+            upload(Some(1), "5 MB", "Windows"),
+            upload(Some(2), "6 MB", "Linux"),
+        ];
+        assert_eq!(
+            find_upload_id(&upload(None, "6 MB", "Linux"), &download_page),
+            Ok(UploadId(2))
+        );
+        assert!(find_upload_id(&upload(None, "6 MB", "Linux"), &download_page[..1]).is_err());
+        assert_eq!(
+            find_upload_id(&upload(None, "5.0 MB", "Windows"), &download_page),
+            Ok(UploadId(1))
+        );
+
+        let download_page = [
+            upload(Some(1), "5 MB", "Windows"),
+            upload(Some(2), "5 MB", "Windows"),
+        ];
+        assert!(find_upload_id(&upload(None, "5 MB", "Windows"), &download_page).is_err());
+    }
+}
@@ -1,9 +1,18 @@
 use anyhow::Context;
-use url::Url;
+use itch_io::drift::DriftReport;
+use itch_io::drift::PageKind;
+use itch_io::GameUrl;
+use std::path::PathBuf;
 
 #[derive(argh::FromArgs)]
 #[argh(description = "a CLI for interacting with itch.io")]
 struct Options {
+    #[argh(
+        option,
+        description = "a json or toml file overriding the css selectors used to parse pages"
+    )]
+    selectors: Option<PathBuf>,
+
     #[argh(subcommand)]
     subcommand: Subcommand,
 }
@@ -12,13 +21,49 @@ struct Options {
 #[argh(subcommand)]
 enum Subcommand {
     GameInfo(GameInfoOptions),
+    CheckParsers(CheckParsersOptions),
 }
 
 #[derive(argh::FromArgs)]
 #[argh(subcommand, name = "game-info", description = "get game info")]
 struct GameInfoOptions {
     #[argh(positional, description = "the url of the game")]
-    url: Url,
+    url: GameUrl,
+}
+
+#[derive(argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "check-parsers",
+    description = "check the page parsers against saved or live pages"
+)]
+struct CheckParsersOptions {
+    #[argh(
+        option,
+        description = "a saved game page or the url of a game page, may be repeated"
+    )]
+    game_page: Vec<String>,
+
+    #[argh(
+        option,
+        description = "a saved download page or the url of a download page, may be repeated"
+    )]
+    download_page: Vec<String>,
+
+    #[argh(
+        option,
+        description = "a saved download lightbox or the url of a game page to get one from, may be repeated"
+    )]
+    lightbox: Vec<String>,
+
+    #[argh(
+        option,
+        description = "a json report from a previous run to compare against"
+    )]
+    baseline: Option<PathBuf>,
+
+    #[argh(option, description = "save the json report to this path")]
+    save_report: Option<PathBuf>,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -35,16 +80,22 @@ fn main() -> anyhow::Result<()> {
 
 async fn async_main(options: Options) -> anyhow::Result<()> {
     let client = itch_io::Client::new();
+    if let Some(path) = options.selectors.as_ref() {
+        let selectors = itch_io::Selectors::from_path(path)
+            .with_context(|| format!("failed to load selectors from \"{}\"", path.display()))?;
+        client.set_selectors(selectors);
+    }
 
     match options.subcommand {
         Subcommand::GameInfo(options) => {
             let game_page = client
-                .get_game_page(options.url.as_str())
+                .get_game_page(&options.url)
                 .await
                 .context("failed to get game page")?;
 
             println!("Title: {}", game_page.title);
             println!("Url: {}", game_page.twitter_url);
+            println!("Canonical Url: {}", game_page.canonical_url);
             println!("CSRF Token: {}", game_page.csrf_token);
             println!(
                 "Html View Url: {}",
@@ -55,39 +106,136 @@ async fn async_main(options: Options) -> anyhow::Result<()> {
                     .unwrap_or("None")
             );
 
-            println!("Downloads:");
-            if game_page.downloads.is_empty() {
+            println!("Uploads:");
+            if game_page.uploads.is_empty() {
                 println!("  None");
             }
 
-            for download in game_page.downloads {
+            for upload in game_page.uploads {
                 let mut id_str_buffer = itoa::Buffer::new();
 
-                println!("  Title: {}", download.title);
-                println!("  Size: {}", download.size);
+                println!("  Title: {}", upload.title);
+                println!(
+                    "  Size: {}",
+                    upload
+                        .size
+                        .map(|size| size.to_string())
+                        .as_deref()
+                        .unwrap_or("unknown")
+                );
                 println!(
                     "  Id: {}",
-                    download
+                    upload
                         .id
-                        .map(|id| id_str_buffer.format(id))
+                        .map(|id| id_str_buffer.format(id.0))
                         .unwrap_or("unknown")
                 );
                 println!("  Platforms:");
-                if download.platforms.is_empty() {
+                if upload.platforms.is_empty() {
                     println!("    None");
                 }
-                for platform in download.platforms {
-                    let platform_str = match platform {
-                        itch_io::Platform::Windows => "Windows",
-                        itch_io::Platform::Linux => "Linux",
-                        itch_io::Platform::MacOs => "MacOs",
-                    };
-                    println!("    {platform_str}");
+                for platform in upload.platforms {
+                    println!("    {platform}");
                     println!();
                 }
                 println!();
             }
         }
+        Subcommand::CheckParsers(options) => {
+            let pages = options
+                .game_page
+                .iter()
+                .map(|source| (PageKind::GamePage, source))
+                .chain(
+                    options
+                        .download_page
+                        .iter()
+                        .map(|source| (PageKind::DownloadPage, source)),
+                )
+                .chain(
+                    options
+                        .lightbox
+                        .iter()
+                        .map(|source| (PageKind::Lightbox, source)),
+                );
+
+            let mut report = DriftReport::new();
+            for (kind, source) in pages {
+                if source.starts_with("https://") || source.starts_with("http://") {
+                    let page_report = client
+                        .check_page(kind, source)
+                        .await
+                        .with_context(|| format!("failed to get \"{source}\""))?;
+                    report.merge(page_report);
+                } else {
+                    let text = std::fs::read_to_string(source)
+                        .with_context(|| format!("failed to read \"{source}\""))?;
+                    let html = scraper::Html::parse_document(&text);
+                    report.check_page(kind, &html, &client.selectors());
+                }
+            }
+
+            let baseline = options
+                .baseline
+                .as_ref()
+                .map(|path| {
+                    let text = std::fs::read_to_string(path)?;
+                    let baseline: DriftReport = serde_json::from_str(&text)?;
+                    anyhow::Ok(baseline)
+                })
+                .transpose()
+                .context("failed to load baseline")?;
+
+            println!("Pages:");
+            if report.pages.is_empty() {
+                println!("  None");
+            }
+            for (kind, pages) in report.pages.iter() {
+                println!("  {kind}: {pages}");
+            }
+            println!();
+
+            println!("Fields:");
+            for (field, stats) in report.fields.iter() {
+                println!(
+                    "  {field}: {}/{} ({:.1}%)",
+                    stats.attempts - stats.failures,
+                    stats.attempts,
+                    stats.success_rate() * 100.0
+                );
+            }
+            println!();
+
+            println!("Unknown Platforms:");
+            if report.unknown_platforms.is_empty() {
+                println!("  None");
+            }
+            for platform in report.unknown_platforms.iter() {
+                println!("  {platform}");
+            }
+            println!();
+
+            if let Some(path) = options.save_report.as_ref() {
+                let json = serde_json::to_string_pretty(&report)?;
+                std::fs::write(path, json)
+                    .with_context(|| format!("failed to save report to \"{}\"", path.display()))?;
+            }
+
+            let regressions = report.regressions(baseline.as_ref());
+            println!("Regressions:");
+            if regressions.is_empty() {
+                println!("  None");
+            }
+            for regression in regressions.iter() {
+                println!("  {regression}");
+            }
+
+            anyhow::ensure!(
+                regressions.is_empty(),
+                "found {} regression(s)",
+                regressions.len()
+            );
+        }
     }
 
     Ok(())
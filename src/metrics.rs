@@ -0,0 +1,214 @@
+//! Request and parser metrics.
+//!
+//! Implement [`Metrics`] and pass it to [`Client::set_metrics`](crate::Client::set_metrics)
+//! to export metrics, or use [`Counters`], which can render them for Prometheus.
+
+use crate::drift::PageKind;
+use crate::request::Endpoint;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A hook for client metrics.
+///
+/// All methods do nothing by default.
+pub trait Metrics: std::fmt::Debug + Send + Sync {
+    /// Called when a request completes.
+    ///
+    /// `status` is `None` if the request failed without a response.
+    fn request(
+        &self,
+        endpoint: Option<Endpoint>,
+        status: Option<http::StatusCode>,
+        bytes: usize,
+        duration: Duration,
+    ) {
+        let _ = (endpoint, status, bytes, duration);
+    }
+
+    /// Called when a request is retried.
+    fn retry(&self, endpoint: Option<Endpoint>) {
+        let _ = endpoint;
+    }
+
+    /// Called when a page is parsed.
+    fn parse(&self, page: PageKind, duration: Duration) {
+        let _ = (page, duration);
+    }
+
+    /// Called for each problem found while parsing a page.
+    ///
+    /// `error` is the name of the error variant, like `MissingTitle`.
+    fn parse_failure(&self, page: PageKind, error: &'static str) {
+        let _ = (page, error);
+    }
+}
+
+/// Metrics kept as counters.
+#[derive(Debug, Default)]
+pub struct Counters {
+    /// The number of requests
+    requests: AtomicU64,
+
+    /// The number of requests that failed without a response
+    request_errors: AtomicU64,
+
+    /// The number of bytes received
+    bytes: AtomicU64,
+
+    /// The number of retries
+    retries: AtomicU64,
+
+    /// The number of pages parsed
+    parses: AtomicU64,
+
+    /// The number of parse failures, by page kind and error variant
+    parse_failures: Mutex<BTreeMap<(PageKind, &'static str), u64>>,
+}
+
+impl Counters {
+    /// Make new counters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of requests.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of requests that failed without a response.
+    pub fn request_errors(&self) -> u64 {
+        self.request_errors.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of bytes received.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of retries.
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of pages parsed.
+    pub fn parses(&self) -> u64 {
+        self.parses.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of parse failures, by page kind and error variant.
+    pub fn parse_failures(&self) -> BTreeMap<(PageKind, &'static str), u64> {
+        self.parse_failures
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .clone()
+    }
+
+    /// Render these counters in the Prometheus text format.
+    pub fn to_prometheus(&self) -> String {
+        let mut output = String::new();
+        let counters = [
+            ("itch_io_requests_total", "Requests sent", self.requests()),
+            (
+                "itch_io_request_errors_total",
+                "Requests that failed without a response",
+                self.request_errors(),
+            ),
+            (
+                "itch_io_response_bytes_total",
+                "Bytes received",
+                self.bytes(),
+            ),
+            ("itch_io_retries_total", "Requests retried", self.retries()),
+            ("itch_io_parses_total", "Pages parsed", self.parses()),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(output, "# HELP {name} {help}");
+            let _ = writeln!(output, "# TYPE {name} counter");
+            let _ = writeln!(output, "{name} {value}");
+        }
+
+        let name = "itch_io_parse_failures_total";
+        let _ = writeln!(output, "# HELP {name} Parse failures by page and error");
+        let _ = writeln!(output, "# TYPE {name} counter");
+        for ((page, error), value) in self.parse_failures() {
+            let _ = writeln!(
+                output,
+                "{name}{{page=\"{page}\",error=\"{error}\"}} {value}"
+            );
+        }
+
+        output
+    }
+}
+
+impl Metrics for Counters {
+    fn request(
+        &self,
+        _endpoint: Option<Endpoint>,
+        status: Option<http::StatusCode>,
+        bytes: usize,
+        _duration: Duration,
+    ) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if status.is_none() {
+            self.request_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn retry(&self, _endpoint: Option<Endpoint>) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn parse(&self, _page: PageKind, _duration: Duration) {
+        self.parses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn parse_failure(&self, page: PageKind, error: &'static str) {
+        *self
+            .parse_failures
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .entry((page, error))
+            .or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::ReplayTransport;
+    use crate::Client;
+    use crate::GameUrl;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn counters() {
+        let transport = ReplayTransport::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_data/cassettes/download_game_page_works.json"
+        ))
+        .expect("failed to load cassette");
+        let counters = Arc::new(Counters::new());
+        let mut client = Client::with_transport(Arc::new(transport));
+        client.set_metrics(counters.clone());
+
+        let url = GameUrl::parse("https://maytch.itch.io/roll-racer").expect("failed to parse url");
+        client
+            .get_game_page(&url)
+            .await
+            .expect("failed to get game page");
+
+        assert_eq!(counters.requests(), 1);
+        assert_eq!(counters.parses(), 1);
+        assert!(counters.bytes() > 0);
+        assert!(counters
+            .to_prometheus()
+            .contains("itch_io_requests_total 1\n"));
+    }
+}
@@ -0,0 +1,580 @@
+//! A persistent download queue.
+//!
+//! [`DownloadQueue`] keeps pending upload downloads in a json file,
+//! along with how much of each file has been written,
+//! so that a long-running process can pick up where it left off after a restart.
+
+use crate::download::ProgressFn;
+use crate::download::RemoteFile;
+use crate::download::RemoteFileFn;
+use crate::time::unix_time;
+use crate::Client;
+use crate::Error;
+use crate::GameUrl;
+use crate::UploadId;
+use futures_util::future::Either;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The default max number of attempts for an item
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// The default delay before the first retry
+const DEFAULT_BASE_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The default max delay between retries
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// How often progress is saved while downloading
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// An error that may occur while using a download queue
+#[derive(Debug, thiserror::Error)]
+pub enum QueueError {
+    /// Failed to read or write the queue file
+    #[error("failed to access queue file")]
+    Io(#[from] std::io::Error),
+
+    /// Invalid queue json
+    #[error("invalid queue file")]
+    Json(#[from] serde_json::Error),
+
+    /// A stored game url is invalid
+    #[error("invalid game url in queue file")]
+    InvalidGameUrl(#[from] crate::types::ParseGameUrlError),
+
+    /// The executor failed to save the queue file
+    #[error("failed to run queue file save")]
+    Executor(#[source] Box<Error>),
+}
+
+/// A pending download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueItem {
+    /// The game page url.
+    ///
+    /// For games on custom domains, this should be the `twitter_url` of the game page.
+    pub game_url: GameUrl,
+
+    /// The upload id
+    pub upload_id: UploadId,
+
+    /// The path to download to
+    pub destination: PathBuf,
+
+    /// The number of bytes from the start of the file that have been written
+    pub offset: u64,
+
+    /// What the server reported about the file when it was last downloaded.
+    ///
+    /// Resuming checks this, so that a file replaced between attempts is downloaded again from the start.
+    pub remote: RemoteFile,
+
+    /// The number of failed attempts
+    pub attempts: u32,
+
+    /// When the item may be tried again, in seconds since the unix epoch
+    pub retry_at: u64,
+
+    /// The error of the last failed attempt
+    pub last_error: Option<String>,
+}
+
+/// A queue item, as stored in the queue file
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct StoredItem {
+    game_url: String,
+    upload_id: u64,
+    destination: PathBuf,
+    #[serde(default)]
+    offset: u64,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    etag: Option<String>,
+    #[serde(default)]
+    last_modified: Option<String>,
+    #[serde(default)]
+    attempts: u32,
+    #[serde(default)]
+    retry_at: u64,
+    #[serde(default)]
+    last_error: Option<String>,
+}
+
+/// The queue file
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct QueueFile {
+    items: Vec<StoredItem>,
+}
+
+/// A queue of upload downloads, persisted to a json file.
+///
+/// Items are downloaded one at a time, in the order they were pushed.
+/// Progress is saved every few seconds, and interrupted downloads resume from the saved offset,
+/// unless the file on the server has changed since.
+/// Failed items are retried with exponential backoff,
+/// and dropped from the queue after too many attempts.
+#[derive(Debug)]
+pub struct DownloadQueue {
+    /// The client downloads are made with
+    client: Client,
+
+    /// The path of the queue file
+    path: PathBuf,
+
+    /// The pending items, shared with progress callbacks
+    items: Arc<Mutex<Vec<QueueItem>>>,
+
+    /// The max number of attempts for an item
+    max_attempts: u32,
+
+    /// The delay before the first retry
+    base_backoff: Duration,
+
+    /// The max delay between retries
+    max_backoff: Duration,
+
+    /// The max number of chunks in flight for a download
+    connections: usize,
+}
+
+impl DownloadQueue {
+    /// Open a queue file, or start an empty queue if it does not exist.
+    ///
+    /// The csrf token for each download is managed by the client,
+    /// see [`Client::get_download_info_with_session`].
+    pub fn open<P>(client: Client, path: P) -> Result<Self, QueueError>
+    where
+        P: Into<PathBuf>,
+    {
+        let path = path.into();
+        let file = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => QueueFile::default(),
+            Err(error) => return Err(error.into()),
+        };
+        let items = file
+            .items
+            .into_iter()
+            .map(|item| {
+                Ok(QueueItem {
+                    game_url: GameUrl::parse(&item.game_url)?,
+                    upload_id: UploadId(item.upload_id),
+                    destination: item.destination,
+                    offset: item.offset,
+                    remote: RemoteFile {
+                        size: item.size,
+                        etag: item.etag,
+                        last_modified: item.last_modified,
+                    },
+                    attempts: item.attempts,
+                    retry_at: item.retry_at,
+                    last_error: item.last_error,
+                })
+            })
+            .collect::<Result<_, QueueError>>()?;
+
+        Ok(Self {
+            client,
+            path,
+            items: Arc::new(Mutex::new(items)),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_backoff: DEFAULT_BASE_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            connections: 1,
+        })
+    }
+
+    /// Set the max number of attempts for an item.
+    ///
+    /// This defaults to 5.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry, and the max delay between retries.
+    ///
+    /// The delay doubles after every failed attempt.
+    /// This defaults to 30 seconds, up to an hour.
+    pub fn with_backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.base_backoff = base;
+        self.max_backoff = max.max(base);
+        self
+    }
+
+    /// Set the max number of chunks in flight for a download.
+    ///
+    /// See [`Download::with_connections`](crate::download::Download::with_connections).
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.connections = connections.max(1);
+        self
+    }
+
+    /// Get a copy of the pending items.
+    pub fn items(&self) -> Vec<QueueItem> {
+        lock(&self.items).clone()
+    }
+
+    /// Add an upload to download to the end of the queue, and save the queue.
+    pub fn push<P>(
+        &self,
+        game_url: GameUrl,
+        upload_id: UploadId,
+        destination: P,
+    ) -> Result<(), QueueError>
+    where
+        P: Into<PathBuf>,
+    {
+        lock(&self.items).push(QueueItem {
+            game_url,
+            upload_id,
+            destination: destination.into(),
+            offset: 0,
+            remote: RemoteFile::default(),
+            attempts: 0,
+            retry_at: 0,
+            last_error: None,
+        });
+        save(&self.path, &self.items)
+    }
+
+    /// Download items until the queue is empty.
+    ///
+    /// Returns the items that were dropped after too many failed attempts, with their last error.
+    /// This only fails if the queue file cannot be saved.
+    pub async fn run(&self) -> Result<Vec<(QueueItem, Error)>, QueueError> {
+        let mut dropped = Vec::new();
+        loop {
+            let next = lock(&self.items)
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, item)| item.retry_at)
+                .map(|(index, item)| (index, item.clone()));
+            let Some((_, item)) = next else {
+                break;
+            };
+
+            let wait = item.retry_at.saturating_sub(unix_time());
+            if wait > 0 {
+                self.client.sleep(Duration::from_secs(wait)).await;
+            }
+
+            let result = self.download(&item).await;
+            self.finish(&item, result, &mut dropped);
+            self.save().await?;
+        }
+
+        Ok(dropped)
+    }
+
+    /// Remove a finished item, or schedule its retry.
+    fn finish(
+        &self,
+        item: &QueueItem,
+        result: Result<(), Error>,
+        dropped: &mut Vec<(QueueItem, Error)>,
+    ) {
+        let mut items = lock(&self.items);
+        let Some(index) = items.iter().position(|queued| same_item(queued, item)) else {
+            return;
+        };
+        match result {
+            Ok(()) => {
+                items.remove(index);
+            }
+            Err(error) => {
+                let queued = &mut items[index];
+                queued.attempts += 1;
+                queued.last_error = Some(error.to_string());
+                if queued.attempts >= self.max_attempts {
+                    dropped.push((items.remove(index), error));
+                } else {
+                    queued.retry_at =
+                        unix_time().saturating_add(self.backoff(queued.attempts).as_secs());
+                }
+            }
+        }
+    }
+
+    /// Get the delay after a number of failed attempts.
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.base_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Download an item, saving its progress as chunks are written.
+    async fn download(&self, item: &QueueItem) -> Result<(), Error> {
+        let download_info = self
+            .client
+            .get_download_info_with_session(&item.game_url, item.upload_id)
+            .await?;
+
+        // Callbacks only update the items, which are saved from here every few seconds.
+        let items = self.items.clone();
+        let key = item.clone();
+        let progress: ProgressFn = Arc::new(move |offset| {
+            update(&items, &key, |queued| queued.offset = offset);
+        });
+        let items = self.items.clone();
+        let key = item.clone();
+        let on_remote_file: RemoteFileFn = Arc::new(move |remote| {
+            update(&items, &key, |queued| queued.remote = remote.clone());
+        });
+
+        let download = self
+            .client
+            .download(&download_info.url, &item.destination)
+            .with_connections(self.connections)
+            .resume_from(item.offset)
+            .if_unchanged(item.remote.clone())
+            .with_progress(progress)
+            .with_remote_file(on_remote_file)
+            .run();
+        let mut download = Box::pin(download);
+        loop {
+            let tick = self.client.sleep(SAVE_INTERVAL);
+            match futures_util::future::select(download, Box::pin(tick)).await {
+                Either::Left((result, _)) => {
+                    result?;
+                    return Ok(());
+                }
+                Either::Right(((), pending)) => {
+                    // Progress is only an optimization, so a failed save is retried with the next tick.
+                    let _ = self.save().await;
+                    download = pending;
+                }
+            }
+        }
+    }
+
+    /// Save the items to the queue file, off the async executor.
+    async fn save(&self) -> Result<(), QueueError> {
+        let path = self.path.clone();
+        let items = self.items.clone();
+        self.client
+            .spawn_blocking(move || save(&path, &items))
+            .await
+            .map_err(|error| QueueError::Executor(Box::new(error)))?
+    }
+}
+
+/// Update the queued item for a download, if it is still queued.
+fn update<F>(items: &Mutex<Vec<QueueItem>>, key: &QueueItem, f: F)
+where
+    F: FnOnce(&mut QueueItem),
+{
+    if let Some(queued) = lock(items).iter_mut().find(|queued| same_item(queued, key)) {
+        f(queued);
+    }
+}
+
+/// Returns true if two items are for the same download.
+fn same_item(a: &QueueItem, b: &QueueItem) -> bool {
+    a.upload_id == b.upload_id && a.destination == b.destination
+}
+
+/// Lock the items.
+fn lock(items: &Mutex<Vec<QueueItem>>) -> std::sync::MutexGuard<'_, Vec<QueueItem>> {
+    items.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+/// Save the items to the queue file.
+fn save(path: &Path, items: &Mutex<Vec<QueueItem>>) -> Result<(), QueueError> {
+    let file = QueueFile {
+        items: lock(items)
+            .iter()
+            .map(|item| StoredItem {
+                game_url: item.game_url.to_string(),
+                upload_id: item.upload_id.0,
+                destination: item.destination.clone(),
+                offset: item.offset,
+                size: item.remote.size,
+                etag: item.remote.etag.clone(),
+                last_modified: item.remote.last_modified.clone(),
+                attempts: item.attempts,
+                retry_at: item.retry_at,
+                last_error: item.last_error.clone(),
+            })
+            .collect(),
+    };
+
+    // Write to a temporary file first, so that a crash never leaves a partial queue.
+    let temp_path = path.with_extension("json.tmp");
+    std::fs::write(&temp_path, serde_json::to_vec_pretty(&file)?)?;
+    std::fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::BoxFuture;
+    use crate::transport::ReplayTransport;
+    use crate::transport::Request;
+    use crate::transport::Response;
+    use crate::transport::Transport;
+
+    /// The file served by [`FileTransport`]
+    const FILE: &str = "0123456789";
+
+    /// A transport that serves download info and a file with an etag, with support for `If-Range`
+    #[derive(Debug)]
+    struct FileTransport;
+
+    impl Transport for FileTransport {
+        fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+            let header = |name| {
+                request
+                    .headers()
+                    .get(name)
+                    .map(|value: &http::HeaderValue| {
+                        value.to_str().expect("invalid header").to_string()
+                    })
+            };
+            let response = if request.uri().host() != Some("files.example.com") {
+                http::Response::builder().body(
+                    br#"{"external": false, "lightbox": "", "url": "https://files.example.com/game.zip"}"#
+                        .to_vec(),
+                )
+            } else {
+                let start = header(http::header::RANGE)
+                    .and_then(|range| {
+                        range
+                            .strip_prefix("bytes=")?
+                            .split_once('-')?
+                            .0
+                            .parse()
+                            .ok()
+                    })
+                    .filter(|_| header(http::header::IF_RANGE).as_deref() == Some("\"v1\""));
+                match start {
+                    Some(start) => http::Response::builder()
+                        .status(http::StatusCode::PARTIAL_CONTENT)
+                        .header(http::header::ETAG, "\"v1\"")
+                        .header(
+                            http::header::CONTENT_RANGE,
+                            format!("bytes {start}-{}/{}", FILE.len() - 1, FILE.len()),
+                        )
+                        .body(FILE.as_bytes()[start..].to_vec()),
+                    None => http::Response::builder()
+                        .header(http::header::ETAG, "\"v1\"")
+                        .body(FILE.into()),
+                }
+            };
+            Box::pin(async move { Ok(response?) })
+        }
+    }
+
+    #[tokio::test]
+    async fn resume() {
+        let dir =
+            std::env::temp_dir().join(format!("itch-io-queue-resume-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create dir");
+        let path = dir.join("queue.json");
+        let unchanged = dir.join("unchanged.zip");
+        let changed = dir.join("changed.zip");
+        let game_url =
+            GameUrl::parse("https://jnohr.itch.io/mrk-borg-free").expect("invalid game url");
+
+        // The first file is still on the server, so only its missing bytes are fetched.
+        // The second was replaced since it was partially downloaded, so it starts over.
+        std::fs::write(&unchanged, "0123garbage").expect("failed to write file");
+        std::fs::write(&changed, "abcd").expect("failed to write file");
+        let queue_file = serde_json::json!({
+            "items": [
+                {
+                    "game_url": game_url.as_str(),
+                    "upload_id": 1,
+                    "destination": unchanged,
+                    "offset": 4,
+                    "size": 10,
+                    "etag": "\"v1\"",
+                },
+                {
+                    "game_url": game_url.as_str(),
+                    "upload_id": 2,
+                    "destination": changed,
+                    "offset": 4,
+                    "size": 10,
+                    "etag": "\"v0\"",
+                },
+            ],
+        });
+        std::fs::write(&path, queue_file.to_string()).expect("failed to write queue file");
+
+        let client = Client::with_transport(Arc::new(FileTransport));
+        client.set_csrf_token(&game_url, "token".into());
+        let queue = DownloadQueue::open(client, &path).expect("failed to open queue");
+        let dropped = queue.run().await.expect("failed to run queue");
+        assert!(dropped.is_empty(), "{dropped:?}");
+        assert!(queue.items().is_empty());
+        assert_eq!(
+            std::fs::read_to_string(&unchanged).expect("failed to read file"),
+            FILE
+        );
+        assert_eq!(
+            std::fs::read_to_string(&changed).expect("failed to read file"),
+            FILE
+        );
+
+        std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    }
+
+    #[test]
+    fn backoff() {
+        let client = Client::with_transport(Arc::new(FileTransport));
+        let queue = DownloadQueue::open(
+            client,
+            std::env::temp_dir().join("itch-io-missing-queue.json"),
+        )
+        .expect("failed to open queue")
+        .with_backoff(Duration::from_secs(10), Duration::from_secs(60));
+        let delays: Vec<u64> = (1..=5)
+            .map(|attempts| queue.backoff(attempts).as_secs())
+            .collect();
+        assert_eq!(delays, [10, 20, 40, 60, 60]);
+        assert_eq!(queue.backoff(100), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn persist() {
+        let dir = std::env::temp_dir().join(format!("itch-io-queue-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create dir");
+        let path = dir.join("queue.json");
+        let client = Client::with_transport(Arc::new(
+            ReplayTransport::load(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/test_data/cassettes/download_game_page_works.json"
+            ))
+            .expect("failed to load cassette"),
+        ));
+
+        let game_url =
+            GameUrl::parse("https://jnohr.itch.io/mrk-borg-free").expect("failed to parse url");
+        let queue = DownloadQueue::open(client.clone(), &path)
+            .expect("failed to open queue")
+            .with_max_attempts(1);
+        queue
+            .push(game_url.clone(), UploadId(2002), dir.join("mork-borg.pdf"))
+            .expect("failed to push");
+
+        let reopened = DownloadQueue::open(client, &path).expect("failed to reopen queue");
+        assert_eq!(reopened.items(), queue.items());
+
+        // The cassette has no file, so the item fails and is dropped.
+        let dropped = queue.run().await.expect("failed to run queue");
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].0.game_url, game_url);
+        assert!(queue.items().is_empty());
+
+        std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    }
+}
@@ -0,0 +1,183 @@
+//! Request builders.
+//!
+//! These build the requests the [`Client`](crate::Client) sends for each endpoint,
+//! so that itch.io can be driven through another http stack.
+//! Responses can be parsed with [`GamePage::from_html`](crate::GamePage::from_html),
+//! [`DownloadPage::from_html`](crate::DownloadPage::from_html),
+//! and the `from_json` functions of the json response types.
+//!
+//! Requests that take a csrf token must be sent with the cookies set by the game page response.
+//! Each request has its [`Endpoint`] in its extensions.
+
+use crate::GameUrl;
+use crate::UploadId;
+use std::ops::Range;
+use url::Url;
+
+/// The base url of the itch.io api
+const API_URL: &str = "https://api.itch.io";
+
+/// A request, with a body
+pub type Request = http::Request<Vec<u8>>;
+
+/// The endpoint a request is for
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum Endpoint {
+    /// Any html page
+    Page,
+
+    /// A game page
+    GamePage,
+
+    /// The download info of an upload
+    DownloadInfo,
+
+    /// The purchase dialog of a game
+    PurchaseDialog,
+
+    /// The download page url of a game
+    DownloadPageUrl,
+
+    /// A download page
+    DownloadPage,
+
+    /// A file, or a range of one, from a download url
+    File,
+
+    /// Upload info from the itch.io api
+    UploadInfo,
+}
+
+impl Endpoint {
+    /// Get the endpoint of a request, if it was made by this module.
+    pub fn of(request: &Request) -> Option<Self> {
+        request.extensions().get().copied()
+    }
+}
+
+/// Build a request for an html page.
+pub fn page(url: &Url) -> Result<Request, http::Error> {
+    get(url.as_str(), Endpoint::Page)
+}
+
+/// Build a request for a game page.
+///
+/// The response can be parsed with [`GamePage::from_html`](crate::GamePage::from_html).
+pub fn game_page(url: &GameUrl) -> Result<Request, http::Error> {
+    get(url.as_str(), Endpoint::GamePage)
+}
+
+/// Build a request for the download info of an upload.
+///
+/// For games on custom domains, `game_url` should be the `twitter_url` of the game page.
+/// The response can be parsed with [`DownloadInfo::from_json`](crate::DownloadInfo::from_json).
+pub fn download_info(
+    game_url: &GameUrl,
+    upload_id: UploadId,
+    csrf_token: &str,
+) -> Result<Request, http::Error> {
+    post_form(
+        &game_url.endpoint(&format!("file/{upload_id}?after_download_lightbox=true")),
+        &[("csrf_token", csrf_token)],
+        Endpoint::DownloadInfo,
+    )
+}
+
+/// Build a request for the purchase dialog of a game.
+///
+/// The response can be parsed with [`PurchaseDialog::from_json`](crate::PurchaseDialog::from_json).
+pub fn purchase_dialog(game_url: &GameUrl) -> Result<Request, http::Error> {
+    get(
+        &game_url.endpoint("purchase?lightbox=true"),
+        Endpoint::PurchaseDialog,
+    )
+}
+
+/// Build a request for the download page url of a game.
+///
+/// For games on custom domains, `game_url` should be the `twitter_url` of the game page.
+/// The response can be parsed with
+/// [`DownloadPageUrlInfo::from_json`](crate::DownloadPageUrlInfo::from_json).
+pub fn download_page_url(game_url: &GameUrl, csrf_token: &str) -> Result<Request, http::Error> {
+    post_form(
+        &game_url.endpoint("download_url"),
+        &[("csrf_token", csrf_token)],
+        Endpoint::DownloadPageUrl,
+    )
+}
+
+/// Build a request for a download page.
+///
+/// The url must be from the `DownloadPageUrlInfo` struct.
+/// The response can be parsed with [`DownloadPage::from_html`](crate::DownloadPage::from_html).
+pub fn download_page(url: &Url) -> Result<Request, http::Error> {
+    get(url.as_str(), Endpoint::DownloadPage)
+}
+
+/// Build a request for a file from a download url.
+///
+/// The url must be from the `DownloadInfo` struct.
+/// If `range` is given, only the bytes in `start..end` are requested.
+/// Servers that do not support ranges respond with the whole file.
+pub fn file(url: &Url, range: Option<Range<u64>>) -> Result<Request, http::Error> {
+    let mut builder = http::Request::get(url.as_str()).extension(Endpoint::File);
+    if let Some(range) = range {
+        builder = builder.header(
+            http::header::RANGE,
+            format!("bytes={}-{}", range.start, range.end.saturating_sub(1)),
+        );
+    }
+    builder.body(Vec::new())
+}
+
+/// Build a request for the info of an upload from the itch.io api.
+///
+/// This needs an itch.io api key.
+/// The response can be parsed with [`UploadInfo::from_json`](crate::UploadInfo::from_json).
+pub fn upload_info(upload_id: UploadId, api_key: &str) -> Result<Request, http::Error> {
+    http::Request::get(format!("{API_URL}/uploads/{upload_id}"))
+        .header(http::header::AUTHORIZATION, format!("Bearer {api_key}"))
+        .extension(Endpoint::UploadInfo)
+        .body(Vec::new())
+}
+
+/// Build a get request.
+fn get(url: &str, endpoint: Endpoint) -> Result<Request, http::Error> {
+    http::Request::get(url).extension(endpoint).body(Vec::new())
+}
+
+/// Build a post request with a form body.
+fn post_form(url: &str, form: &[(&str, &str)], endpoint: Endpoint) -> Result<Request, http::Error> {
+    let body = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(form)
+        .finish();
+    http::Request::post(url)
+        .header(
+            http::header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded",
+        )
+        .extension(endpoint)
+        .body(body.into_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn download_info_request() {
+        let game_url =
+            GameUrl::parse("https://tumblewed.itch.io/doghouse-2").expect("failed to parse url");
+        let request =
+            download_info(&game_url, UploadId(2001), "a+b").expect("failed to build request");
+
+        assert_eq!(request.method(), http::Method::POST);
+        assert_eq!(
+            request.uri(),
+            "https://tumblewed.itch.io/doghouse-2/file/2001?after_download_lightbox=true"
+        );
+        assert_eq!(request.body(), b"csrf_token=a%2Bb");
+        assert_eq!(Endpoint::of(&request), Some(Endpoint::DownloadInfo));
+    }
+}
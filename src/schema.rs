@@ -0,0 +1,165 @@
+//! The versioned serde schema for public types.
+//!
+//! All public data types implement `Serialize` and `Deserialize` when the `serde` feature is enabled.
+//! Values meant to be stored should be wrapped in a [`Versioned`],
+//! which records the [`SCHEMA_VERSION`] they were written with.
+//!
+//! # Compatibility
+//! * New optional fields may be added without changing the schema version.
+//!   Older data missing these fields will deserialize with the field set to `None`.
+//! * Removing, renaming, or changing the type of a field bumps the schema version.
+//! * Data written with a newer schema version than this crate supports fails to deserialize,
+//!   instead of silently losing information.
+//!
+//! # Representation
+//! * `GameUrl` is stored as its normalized url string, like `https://tumblewed.itch.io/doghouse-2`.
+//! * `GameId` and `UploadId` are stored as numbers.
+//! * `ByteSize` is stored as its display string, like `1.5 GB`, preserving its precision.
+//! * `Platform` is stored as a string, like `Windows`.
+//!   Unknown platforms are stored as their itch.io icon class.
+//!
+//! The full schema can be exported with [`json_schema`] when the `json-schema` feature is enabled.
+
+/// The current schema version.
+///
+/// # History
+/// * `1`: Initial version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A value tagged with the schema version it was written with.
+#[derive(Debug, Clone, serde::Serialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Versioned<T> {
+    /// The schema version
+    pub schema_version: u32,
+
+    /// The value
+    pub data: T,
+}
+
+impl<T> Versioned<T> {
+    /// Tag a value with the current schema version.
+    pub fn new(data: T) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            data,
+        }
+    }
+
+    /// Get the inner value.
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+impl<'de, T> serde::Deserialize<'de> for Versioned<T>
+where
+    T: serde::Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct Unchecked<T> {
+            schema_version: u32,
+            data: T,
+        }
+
+        let Unchecked {
+            schema_version,
+            data,
+        } = Unchecked::deserialize(deserializer)?;
+        if schema_version > SCHEMA_VERSION {
+            return Err(serde::de::Error::custom(format_args!(
+                "unsupported schema version {schema_version}, expected at most {SCHEMA_VERSION}"
+            )));
+        }
+
+        Ok(Self {
+            schema_version,
+            data,
+        })
+    }
+}
+
+/// Get the JSON Schema for a versioned value.
+///
+/// For example, `json_schema::<GamePage>()` describes a stored `Versioned<GamePage>`.
+#[cfg(feature = "json-schema")]
+pub fn json_schema<T>() -> schemars::Schema
+where
+    T: schemars::JsonSchema,
+{
+    schemars::schema_for!(Versioned<T>)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::GamePage;
+
+    #[test]
+    fn round_trip() {
+        let html = scraper::Html::parse_document(
+            r#"<html>
+<head>
+    <meta name="twitter:url" content="https://tumblewed.itch.io/doghouse-2">
+    <meta name="itch:path" content="games/1234">
+    <meta name="csrf_token" value="token">
+</head>
+<body>
+    <h1 class="game_title">Doghouse 2</h1>
+    <div class="upload">
+        <a class="button download_btn" data-upload_id="5678">Download</a>
+        <strong class="name">doghouse.zip</strong>
+        <span class="file_size"><span>1.5 GB</span></span>
+        <span class="download_platforms"><span class="icon icon-psp"></span></span>
+    </div>
+</body>
+</html>"#,
+        );
+        let game_page = GamePage::from_html(&html).expect("failed to parse");
+
+        let json = serde_json::to_string(&Versioned::new(game_page)).expect("failed to serialize");
+        let versioned: Versioned<GamePage> =
+            serde_json::from_str(&json).expect("failed to deserialize");
+        assert_eq!(versioned.schema_version, SCHEMA_VERSION);
+
+        let game_page = versioned.into_inner();
+        assert_eq!(
+            game_page.twitter_url.as_str(),
+            "https://tumblewed.itch.io/doghouse-2"
+        );
+        assert_eq!(
+            game_page.uploads[0]
+                .size
+                .map(|size| size.to_string())
+                .as_deref(),
+            Some("1.5 GB")
+        );
+        assert_eq!(
+            game_page.uploads[0].platforms,
+            [crate::Platform::Other("psp".into())]
+        );
+    }
+
+    #[test]
+    fn newer_schema_version() {
+        let json = format!(r#"{{"schema_version": {}, "data": 1}}"#, SCHEMA_VERSION + 1);
+        assert!(serde_json::from_str::<Versioned<u64>>(&json).is_err());
+    }
+}
+
+#[cfg(all(test, feature = "json-schema"))]
+mod json_schema_test {
+    use super::*;
+    use crate::GamePage;
+
+    #[test]
+    fn game_page_schema() {
+        let schema = serde_json::to_value(json_schema::<GamePage>()).expect("failed to serialize");
+        assert_eq!(schema["properties"]["schema_version"]["type"], "integer");
+        assert_eq!(schema["$defs"]["GameUrl"]["format"], "uri");
+    }
+}
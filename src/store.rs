@@ -0,0 +1,397 @@
+//! A content-addressed store for downloaded files.
+//!
+//! Files are stored once, as blobs named by their sha256 hash,
+//! so identical uploads shared by many games only take up space once.
+//! md5 collisions are easy to make, so the md5 hashes itch.io reports are only used to verify downloads.
+//! Each game gets a directory with its [`Manifest`](crate::game_download::Manifest)
+//! and a link to the blob of each upload:
+//!
+//! ```text
+//! root/
+//!     blobs/
+//!         d7/
+//!             d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592
+//!     games/
+//!         tumblewed/
+//!             doghouse-2/
+//!                 manifest.json
+//!                 doghouse.zip -> root/blobs/d7/d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592
+//! ```
+//!
+//! Download a game into a store with [`Client::download_game_to_store`](crate::Client::download_game_to_store).
+
+use crate::download::sha256_file;
+use crate::game_download::MANIFEST_FILE_NAME;
+use crate::schema::Versioned;
+use crate::GameUrl;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The name of the directory blobs are kept in
+const BLOBS_DIR: &str = "blobs";
+
+/// The name of the directory game directories are kept in
+const GAMES_DIR: &str = "games";
+
+/// The directory name used in place of a slug, for custom domains that point to a single game
+const NO_SLUG: &str = "_";
+
+/// An error that may occur while using a store
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    /// Failed to access the store
+    #[error("failed to access store")]
+    Io(#[from] std::io::Error),
+
+    /// A manifest in the store could not be read.
+    ///
+    /// Garbage collection stops instead of deleting blobs the manifest might reference.
+    #[error("invalid manifest `{}`", path.display())]
+    InvalidManifest {
+        /// The path of the manifest
+        path: PathBuf,
+
+        /// The error
+        #[source]
+        error: serde_json::Error,
+    },
+
+    /// A hash is not a valid sha256 hash
+    #[error("invalid sha256 hash `{0}`")]
+    InvalidHash(String),
+}
+
+/// The result of a garbage collection
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// The number of blobs that were kept
+    pub blobs_kept: usize,
+
+    /// The number of blobs that were removed
+    pub blobs_removed: usize,
+
+    /// The number of bytes that were freed
+    pub bytes_freed: u64,
+}
+
+/// The parts of a manifest the garbage collector needs
+#[derive(Debug, serde::Deserialize)]
+struct ManifestHashes {
+    uploads: Vec<UploadHash>,
+}
+
+/// The parts of a manifest upload the garbage collector needs
+#[derive(Debug, serde::Deserialize)]
+struct UploadHash {
+    #[serde(default)]
+    sha256_hash: Option<String>,
+}
+
+/// A content-addressed store for downloaded files.
+///
+/// This only touches the filesystem, so its methods block.
+#[derive(Debug, Clone)]
+pub struct Store {
+    /// The root directory, made absolute so links stay valid from any game directory
+    root: PathBuf,
+}
+
+impl Store {
+    /// Open a store, creating its directories if they do not exist.
+    pub fn open<P>(root: P) -> Result<Self, StoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let root = root.as_ref();
+        std::fs::create_dir_all(root.join(BLOBS_DIR))?;
+        std::fs::create_dir_all(root.join(GAMES_DIR))?;
+        Ok(Self {
+            root: root.canonicalize()?,
+        })
+    }
+
+    /// Get the root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the directory a game is downloaded to.
+    ///
+    /// This is `games/{creator}/{slug}` for itch.io urls, and `games/{host}/{slug}` for custom domains.
+    pub fn game_dir(&self, url: &GameUrl) -> PathBuf {
+        let owner = url
+            .creator()
+            .or_else(|| url.as_url().host_str())
+            .unwrap_or(NO_SLUG);
+        self.root
+            .join(GAMES_DIR)
+            .join(owner)
+            .join(url.slug().unwrap_or(NO_SLUG))
+    }
+
+    /// Get the path of the blob for a sha256 hash.
+    ///
+    /// The blob may not exist.
+    pub fn blob_path(&self, sha256_hash: &str) -> Result<PathBuf, StoreError> {
+        if sha256_hash.len() != 64 || !sha256_hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(StoreError::InvalidHash(sha256_hash.into()));
+        }
+        let sha256_hash = sha256_hash.to_ascii_lowercase();
+        Ok(self
+            .root
+            .join(BLOBS_DIR)
+            .join(&sha256_hash[..2])
+            .join(sha256_hash))
+    }
+
+    /// Returns true if the store has a blob for a sha256 hash.
+    pub fn contains(&self, sha256_hash: &str) -> bool {
+        self.blob_path(sha256_hash)
+            .map(|path| path.is_file())
+            .unwrap_or(false)
+    }
+
+    /// Move a file into the store, returning its sha256 hash.
+    ///
+    /// The blob is made read-only, as it may be shared by many games.
+    /// If the store already has the file, the file is removed instead.
+    pub fn insert<P>(&self, path: P) -> Result<String, StoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let sha256_hash = sha256_file(path)?;
+        self.insert_with_hash(path, &sha256_hash)?;
+        Ok(sha256_hash)
+    }
+
+    /// Move a file with a known sha256 hash into the store.
+    pub(crate) fn insert_with_hash(
+        &self,
+        path: &Path,
+        sha256_hash: &str,
+    ) -> Result<(), StoreError> {
+        let blob_path = self.blob_path(sha256_hash)?;
+        if blob_path.is_file() {
+            std::fs::remove_file(path)?;
+            return Ok(());
+        }
+
+        if let Some(parent) = blob_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if std::fs::rename(path, &blob_path).is_err() {
+            // Renaming fails across filesystems, so copy next to the blob first,
+            // to never leave a partial blob behind.
+            let temp_path = blob_path.with_extension("tmp");
+            std::fs::copy(path, &temp_path)?;
+            std::fs::rename(&temp_path, &blob_path)?;
+            std::fs::remove_file(path)?;
+        }
+
+        // Blobs are shared by every game linking to them, so writing through a link must fail.
+        let mut permissions = std::fs::metadata(&blob_path)?.permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&blob_path, permissions)?;
+
+        Ok(())
+    }
+
+    /// Link a path to the blob for a sha256 hash, replacing anything at the path.
+    ///
+    /// This makes a symlink on unix, and a hard link elsewhere.
+    /// Either way, the file at the path is read-only;
+    /// to change it, replace the link with a copy first.
+    pub fn link<P>(&self, sha256_hash: &str, path: P) -> Result<(), StoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let blob_path = self.blob_path(sha256_hash)?;
+        match std::fs::remove_file(path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(blob_path, path)?;
+        #[cfg(not(unix))]
+        std::fs::hard_link(blob_path, path)?;
+
+        Ok(())
+    }
+
+    /// Remove every blob that no manifest or link in the store references.
+    ///
+    /// Links are checked too, so a game dir whose last download failed keeps the files it still links to.
+    /// Do not run this while a game is being downloaded to the store,
+    /// as blobs are added before the manifest that references them is written.
+    pub fn gc(&self) -> Result<GcReport, StoreError> {
+        let mut referenced = HashSet::new();
+        let mut dirs = vec![self.root.join(GAMES_DIR)];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    dirs.push(entry.path());
+                } else if file_type.is_symlink() {
+                    let target = std::fs::read_link(entry.path())?;
+                    if target.starts_with(self.root.join(BLOBS_DIR)) {
+                        if let Some(name) = target.file_name() {
+                            referenced.insert(name.to_string_lossy().to_ascii_lowercase());
+                        }
+                    }
+                } else if file_type.is_file() && entry.file_name() == MANIFEST_FILE_NAME {
+                    let path = entry.path();
+                    let text = std::fs::read_to_string(&path)?;
+                    let manifest: Versioned<ManifestHashes> = serde_json::from_str(&text)
+                        .map_err(|error| StoreError::InvalidManifest { path, error })?;
+                    referenced.extend(
+                        manifest
+                            .into_inner()
+                            .uploads
+                            .into_iter()
+                            .filter_map(|upload| upload.sha256_hash)
+                            .map(|sha256_hash| sha256_hash.to_ascii_lowercase()),
+                    );
+                }
+            }
+        }
+
+        let mut report = GcReport::default();
+        for prefix in std::fs::read_dir(self.root.join(BLOBS_DIR))? {
+            let prefix = prefix?;
+            if !prefix.file_type()?.is_dir() {
+                continue;
+            }
+            for blob in std::fs::read_dir(prefix.path())? {
+                let blob = blob?;
+                let name = blob.file_name();
+                if referenced.contains(name.to_string_lossy().as_ref()) {
+                    report.blobs_kept += 1;
+                    continue;
+                }
+                let metadata = blob.metadata()?;
+
+                // Read-only files cannot be removed on windows.
+                #[cfg(windows)]
+                {
+                    let mut permissions = metadata.permissions();
+                    permissions.set_readonly(false);
+                    std::fs::set_permissions(blob.path(), permissions)?;
+                }
+
+                std::fs::remove_file(blob.path())?;
+                report.blobs_removed += 1;
+                report.bytes_freed += metadata.len();
+            }
+
+            // Only empty prefix directories can be removed, so failures are expected.
+            let _ = std::fs::remove_dir(prefix.path());
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dedup_and_gc() {
+        let dir = std::env::temp_dir().join(format!("itch-io-store-test-{}", std::process::id()));
+        let store = Store::open(dir.join("store")).expect("failed to open store");
+        let game_url =
+            GameUrl::parse("https://tumblewed.itch.io/doghouse-2").expect("failed to parse url");
+        let game_dir = store.game_dir(&game_url);
+        assert!(game_dir.ends_with("games/tumblewed/doghouse-2"));
+        std::fs::create_dir_all(&game_dir).expect("failed to create game dir");
+
+        // Two copies of the same file only take up one blob.
+        let kept = game_dir.join("a.txt");
+        let duplicate = game_dir.join("b.txt");
+        std::fs::write(&kept, "abc").expect("failed to write file");
+        std::fs::write(&duplicate, "abc").expect("failed to write file");
+        let kept_hash = store.insert(&kept).expect("failed to insert");
+        let duplicate_hash = store.insert(&duplicate).expect("failed to insert");
+        assert_eq!(
+            kept_hash,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(kept_hash, duplicate_hash);
+        store.link(&kept_hash, &kept).expect("failed to link");
+        assert_eq!(
+            std::fs::read_to_string(&kept).expect("failed to read file"),
+            "abc"
+        );
+        assert!(std::fs::metadata(&kept)
+            .expect("failed to get metadata")
+            .permissions()
+            .readonly());
+
+        let orphan = game_dir.join("c.txt");
+        std::fs::write(&orphan, "orphan").expect("failed to write file");
+        let orphan_hash = store.insert(&orphan).expect("failed to insert");
+
+        let manifest = serde_json::json!({
+            "schema_version": crate::schema::SCHEMA_VERSION,
+            "data": { "uploads": [{ "sha256_hash": kept_hash }] },
+        });
+        std::fs::write(game_dir.join(MANIFEST_FILE_NAME), manifest.to_string())
+            .expect("failed to write manifest");
+
+        let report = store.gc().expect("failed to gc");
+        assert_eq!(
+            report,
+            GcReport {
+                blobs_kept: 1,
+                blobs_removed: 1,
+                bytes_freed: 6,
+            }
+        );
+        assert!(store.contains(&kept_hash));
+        assert!(!store.contains(&orphan_hash));
+
+        std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    }
+
+    // Hard links keep their data when the blob is removed, so only symlinks can dangle.
+    #[cfg(unix)]
+    #[test]
+    fn gc_keeps_linked_blobs() {
+        let dir =
+            std::env::temp_dir().join(format!("itch-io-store-link-test-{}", std::process::id()));
+        let store = Store::open(dir.join("store")).expect("failed to open store");
+        let game_url =
+            GameUrl::parse("https://tumblewed.itch.io/doghouse-2").expect("failed to parse url");
+        let game_dir = store.game_dir(&game_url);
+        std::fs::create_dir_all(&game_dir).expect("failed to create game dir");
+
+        let linked = game_dir.join("a.txt");
+        std::fs::write(&linked, "abc").expect("failed to write file");
+        let hash = store.insert(&linked).expect("failed to insert");
+        store.link(&hash, &linked).expect("failed to link");
+
+        // A re-run that failed to download the upload leaves its entry without a hash.
+        let manifest = serde_json::json!({
+            "schema_version": crate::schema::SCHEMA_VERSION,
+            "data": { "uploads": [{ "sha256_hash": null }] },
+        });
+        std::fs::write(game_dir.join(MANIFEST_FILE_NAME), manifest.to_string())
+            .expect("failed to write manifest");
+
+        let report = store.gc().expect("failed to gc");
+        assert_eq!(report.blobs_removed, 0);
+        assert!(store.contains(&hash));
+        assert_eq!(
+            std::fs::read_to_string(&linked).expect("failed to read file"),
+            "abc"
+        );
+
+        std::fs::remove_dir_all(&dir).expect("failed to remove dir");
+    }
+}
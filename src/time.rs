@@ -0,0 +1,8 @@
+use std::time::SystemTime;
+
+/// Get the current time, in seconds since the unix epoch.
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
@@ -0,0 +1,145 @@
+/// A disk cache
+mod cache;
+/// Cassettes, for recording and replaying requests
+mod cassette;
+
+pub use self::cache::CacheError;
+pub use self::cache::CachingTransport;
+pub use self::cassette::BodyEncoding;
+pub use self::cassette::Cassette;
+pub use self::cassette::CassetteError;
+pub use self::cassette::Interaction;
+pub use self::cassette::RecordedRequest;
+pub use self::cassette::RecordedResponse;
+pub use self::cassette::RecordingTransport;
+pub use self::cassette::ReplayTransport;
+pub use crate::request::Request;
+use crate::Error;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use std::future::Future;
+use std::pin::Pin;
+use url::Url;
+
+/// A boxed future
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A response received from a transport
+pub type Response = http::Response<Vec<u8>>;
+
+/// A response body, received in pieces
+pub type BodyStream = BoxStream<'static, Result<Vec<u8>, Error>>;
+
+/// A response received from a transport, with a body that has not been read yet
+pub type StreamingResponse = http::Response<BodyStream>;
+
+/// The final url of a response, after redirects.
+///
+/// Transports should add this to the extensions of responses that were redirected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseUrl(pub Url);
+
+/// A way to send http requests.
+///
+/// The client sends all of its requests through one of these,
+/// so that they can be recorded, replayed, or sent through another http stack.
+/// Transports are responsible for cookies.
+pub trait Transport: std::fmt::Debug + Send + Sync {
+    /// Send a request.
+    ///
+    /// This should not fail on error status codes.
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>>;
+
+    /// Send a request, without waiting for the body.
+    ///
+    /// The client uses this for file downloads, so large files are never held in memory.
+    /// By default this calls [`Transport::send`] and yields the whole body at once,
+    /// so transports that can stream should override it.
+    /// This should not fail on error status codes.
+    fn send_streaming(&self, request: Request) -> BoxFuture<'_, Result<StreamingResponse, Error>> {
+        Box::pin(async move { Ok(into_streaming(self.send(request).await?)) })
+    }
+}
+
+/// Turn a response into a streaming response that yields the whole body at once.
+pub(crate) fn into_streaming(response: Response) -> StreamingResponse {
+    response.map(|body| futures_util::stream::once(async { Ok(body) }).boxed())
+}
+
+/// A transport using reqwest.
+///
+/// This keeps a cookie store.
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    /// The inner http client
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    /// Make a new transport.
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .cookie_store(true)
+                .build()
+                .expect("failed to build itch.io client"),
+        }
+    }
+
+    /// Make a new transport from a reqwest client.
+    ///
+    /// The client should have a cookie store.
+    pub fn from_client(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReqwestTransport {
+    /// Send a request, returning the response builder and the response with the unread body.
+    async fn execute(
+        &self,
+        request: Request,
+    ) -> Result<(http::response::Builder, reqwest::Response), Error> {
+        let request = reqwest::Request::try_from(request)?;
+        let response = self.client.execute(request).await?;
+
+        let mut builder = http::Response::builder()
+            .status(response.status())
+            .version(response.version())
+            .extension(ResponseUrl(response.url().clone()));
+        if let Some(headers) = builder.headers_mut() {
+            *headers = response.headers().clone();
+        }
+
+        Ok((builder, response))
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        Box::pin(async move {
+            let (builder, response) = self.execute(request).await?;
+            let body = response.bytes().await?;
+
+            Ok(builder.body(body.to_vec())?)
+        })
+    }
+
+    fn send_streaming(&self, request: Request) -> BoxFuture<'_, Result<StreamingResponse, Error>> {
+        Box::pin(async move {
+            let (builder, response) = self.execute(request).await?;
+            let body = futures_util::stream::try_unfold(response, |mut response| async move {
+                let chunk = response.chunk().await?;
+                Ok(chunk.map(|chunk| (chunk.to_vec(), response)))
+            });
+
+            Ok(builder.body(body.boxed())?)
+        })
+    }
+}
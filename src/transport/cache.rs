@@ -0,0 +1,344 @@
+use super::cassette::RecordedResponse;
+use super::into_streaming;
+use super::BoxFuture;
+use super::Request;
+use super::Response;
+use super::StreamingResponse;
+use super::Transport;
+use crate::request::Endpoint;
+use crate::time::unix_time;
+use crate::Error;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// An error that may occur while using the cache
+#[derive(Debug, thiserror::Error)]
+pub enum CacheError {
+    /// Failed to read or write a cache entry
+    #[error("failed to access cache")]
+    Io(#[from] std::io::Error),
+
+    /// Failed to serialize a cache entry
+    #[error("invalid cache entry")]
+    Json(#[from] serde_json::Error),
+
+    /// The cache is offline and the request is not cached
+    #[error("`{uri}` is not cached")]
+    NotCached {
+        /// The request uri
+        uri: String,
+    },
+}
+
+/// A cached response
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct CacheEntry {
+    /// The request uri
+    uri: String,
+
+    /// When the response was stored or last revalidated, in seconds since the unix epoch
+    stored_at: u64,
+
+    /// The response
+    response: RecordedResponse,
+}
+
+impl CacheEntry {
+    /// Get a header of the cached response.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.response
+            .headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A transport that caches responses on disk.
+///
+/// Only get requests are cached, keyed by url.
+/// Post requests, which carry csrf tokens, download info requests,
+/// which return signed urls, file requests and api requests always go to the inner transport.
+///
+/// Entries younger than the ttl of their [`Endpoint`] are served as is.
+/// Older entries are revalidated with `If-None-Match` and `If-Modified-Since`.
+/// The default ttl is zero, so entries are always revalidated.
+///
+/// Requests with `Cache-Control: no-cache` skip the cache and always go to the inner transport,
+/// though their responses are still stored.
+/// [`Client::refresh_csrf_token`](crate::Client::refresh_csrf_token) uses this,
+/// as game pages served from the cache carry the csrf token of the session they were fetched with.
+/// Cookies are not cached.
+#[derive(Debug)]
+pub struct CachingTransport {
+    /// The transport requests are sent through
+    inner: Arc<dyn Transport>,
+
+    /// The directory entries are stored in
+    dir: PathBuf,
+
+    /// The ttls of each endpoint
+    ttls: HashMap<Endpoint, Duration>,
+
+    /// The ttl of endpoints without one
+    default_ttl: Duration,
+
+    /// Whether to serve purely from the cache
+    offline: bool,
+}
+
+impl CachingTransport {
+    /// Cache responses from `inner` in `dir`.
+    pub fn new<P>(inner: Arc<dyn Transport>, dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            inner,
+            dir: dir.into(),
+            ttls: HashMap::new(),
+            default_ttl: Duration::ZERO,
+            offline: false,
+        }
+    }
+
+    /// Set the ttl of an endpoint.
+    pub fn with_ttl(mut self, endpoint: Endpoint, ttl: Duration) -> Self {
+        self.ttls.insert(endpoint, ttl);
+        self
+    }
+
+    /// Set the ttl of endpoints without one.
+    ///
+    /// This is also used for requests without an endpoint.
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Set whether to serve purely from the cache.
+    ///
+    /// In offline mode, cached entries are served regardless of their age,
+    /// and all other requests fail with [`CacheError::NotCached`].
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Returns true if a request may be cached.
+    fn is_cacheable(request: &Request) -> bool {
+        request.method() == http::Method::GET
+            && !matches!(
+                Endpoint::of(request),
+                Some(Endpoint::DownloadInfo | Endpoint::File | Endpoint::UploadInfo)
+            )
+    }
+
+    /// Returns true if a request asks to skip the cache.
+    fn is_no_cache(request: &Request) -> bool {
+        request
+            .headers()
+            .get_all(http::header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.contains("no-cache"))
+    }
+
+    /// Get the ttl of a request.
+    fn ttl(&self, request: &Request) -> Duration {
+        Endpoint::of(request)
+            .and_then(|endpoint| self.ttls.get(&endpoint).copied())
+            .unwrap_or(self.default_ttl)
+    }
+
+    /// Get the path of the entry for a uri.
+    fn entry_path(&self, uri: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.json", fnv1a(uri.as_bytes())))
+    }
+
+    /// Load the entry for a uri.
+    ///
+    /// Missing and unreadable entries are treated as not cached.
+    async fn load(&self, uri: &str) -> Option<CacheEntry> {
+        let text = tokio::fs::read_to_string(self.entry_path(uri)).await.ok()?;
+        let entry: CacheEntry = serde_json::from_str(&text).ok()?;
+
+        // Guard against hash collisions.
+        (entry.uri == uri).then_some(entry)
+    }
+
+    /// Store an entry.
+    async fn store(&self, entry: &CacheEntry) -> Result<(), CacheError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        // Write to a temporary file first, so that readers never see a partial entry.
+        let path = self.entry_path(&entry.uri);
+        let temp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&temp_path, serde_json::to_vec(entry)?).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+
+        Ok(())
+    }
+}
+
+impl Transport for CachingTransport {
+    fn send(&self, mut request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        Box::pin(async move {
+            let uri = request.uri().to_string();
+            if !Self::is_cacheable(&request) {
+                if self.offline {
+                    return Err(CacheError::NotCached { uri }.into());
+                }
+                return self.inner.send(request).await;
+            }
+
+            let now = unix_time();
+            let entry = if Self::is_no_cache(&request) && !self.offline {
+                None
+            } else {
+                self.load(&uri).await
+            };
+            match entry.as_ref() {
+                Some(entry) => {
+                    let age = Duration::from_secs(now.saturating_sub(entry.stored_at));
+                    if self.offline || age < self.ttl(&request) {
+                        return entry.response.to_response();
+                    }
+
+                    let headers = request.headers_mut();
+                    if let Some(etag) = entry.header("etag") {
+                        headers.insert(
+                            http::header::IF_NONE_MATCH,
+                            etag.parse().map_err(http::Error::from)?,
+                        );
+                    }
+                    if let Some(last_modified) = entry.header("last-modified") {
+                        headers.insert(
+                            http::header::IF_MODIFIED_SINCE,
+                            last_modified.parse().map_err(http::Error::from)?,
+                        );
+                    }
+                }
+                None if self.offline => {
+                    return Err(CacheError::NotCached { uri }.into());
+                }
+                None => {}
+            }
+
+            let response = self.inner.send(request).await?;
+            if response.status() == http::StatusCode::NOT_MODIFIED {
+                if let Some(mut entry) = entry {
+                    entry.stored_at = now;
+                    self.store(&entry).await?;
+                    return entry.response.to_response();
+                }
+            }
+
+            let no_store = response
+                .headers()
+                .get_all(http::header::CACHE_CONTROL)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .any(|value| value.contains("no-store"));
+            if response.status().is_success() && !no_store {
+                self.store(&CacheEntry {
+                    uri,
+                    stored_at: now,
+                    response: RecordedResponse::new(&response),
+                })
+                .await?;
+            }
+
+            Ok(response)
+        })
+    }
+
+    fn send_streaming(&self, request: Request) -> BoxFuture<'_, Result<StreamingResponse, Error>> {
+        Box::pin(async move {
+            if Self::is_cacheable(&request) || self.offline {
+                return Ok(into_streaming(self.send(request).await?));
+            }
+            self.inner.send_streaming(request).await
+        })
+    }
+}
+
+/// Hash bytes with 64 bit FNV-1a.
+///
+/// This is stable across builds, unlike the std hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    /// A transport that serves a page with an etag, counting requests
+    #[derive(Debug, Default)]
+    struct EtagTransport {
+        requests: AtomicUsize,
+        conditional_requests: AtomicUsize,
+    }
+
+    impl Transport for EtagTransport {
+        fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let builder = if request.headers().contains_key(http::header::IF_NONE_MATCH) {
+                self.conditional_requests.fetch_add(1, Ordering::SeqCst);
+                http::Response::builder().status(http::StatusCode::NOT_MODIFIED)
+            } else {
+                http::Response::builder().header(http::header::ETAG, "\"1\"")
+            };
+            let response = builder.body(b"<html></html>".to_vec());
+            Box::pin(async move { Ok(response?) })
+        }
+    }
+
+    #[tokio::test]
+    async fn revalidate() {
+        let dir = std::env::temp_dir().join(format!("itch-io-cache-test-{}", std::process::id()));
+        let inner = Arc::new(EtagTransport::default());
+        let cache = CachingTransport::new(inner.clone(), &dir);
+        let url =
+            url::Url::parse("https://tumblewed.itch.io/doghouse-2").expect("failed to parse url");
+
+        for _ in 0..2 {
+            let response = cache
+                .send(crate::request::page(&url).expect("failed to build request"))
+                .await
+                .expect("failed to send request");
+            assert_eq!(response.status(), http::StatusCode::OK);
+            assert_eq!(response.body(), b"<html></html>");
+        }
+        assert_eq!(inner.requests.load(Ordering::SeqCst), 2);
+
+        let offline = CachingTransport::new(inner.clone(), &dir).offline(true);
+        offline
+            .send(crate::request::page(&url).expect("failed to build request"))
+            .await
+            .expect("failed to serve from cache");
+        assert_eq!(inner.requests.load(Ordering::SeqCst), 2);
+
+        // Requests that skip the cache are neither served from it nor revalidated.
+        let cache = CachingTransport::new(inner.clone(), &dir)
+            .with_default_ttl(Duration::from_secs(60 * 60));
+        let mut request = crate::request::page(&url).expect("failed to build request");
+        request.headers_mut().insert(
+            http::header::CACHE_CONTROL,
+            http::HeaderValue::from_static("no-cache"),
+        );
+        cache.send(request).await.expect("failed to send request");
+        assert_eq!(inner.requests.load(Ordering::SeqCst), 3);
+        assert_eq!(inner.conditional_requests.load(Ordering::SeqCst), 1);
+
+        std::fs::remove_dir_all(&dir).expect("failed to remove cache");
+    }
+}
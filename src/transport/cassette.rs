@@ -0,0 +1,335 @@
+use super::BoxFuture;
+use super::Request;
+use super::Response;
+use super::ResponseUrl;
+use super::Transport;
+use crate::Error;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use url::Url;
+
+/// Headers that are never recorded, as they hold credentials
+const REDACTED_HEADERS: &[http::HeaderName] =
+    &[http::header::SET_COOKIE, http::header::AUTHORIZATION];
+
+/// An error that may occur while using a cassette
+#[derive(Debug, thiserror::Error)]
+pub enum CassetteError {
+    /// Failed to read or write the cassette file
+    #[error("failed to access cassette file")]
+    Io(#[from] std::io::Error),
+
+    /// Invalid cassette json
+    #[error("invalid cassette")]
+    Json(#[from] serde_json::Error),
+
+    /// A recorded body is not valid base64
+    #[error("invalid base64 body")]
+    InvalidBody(#[from] base64::DecodeError),
+
+    /// No recorded interaction matched a request
+    #[error("no recorded interaction for {method} `{uri}`")]
+    NoMatch {
+        /// The request method
+        method: String,
+
+        /// The request uri
+        uri: String,
+    },
+}
+
+/// A recorded request
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RecordedRequest {
+    /// The method, like `GET`
+    pub method: String,
+
+    /// The uri
+    pub uri: String,
+
+    /// The body, as utf8
+    #[serde(default)]
+    pub body: String,
+}
+
+impl RecordedRequest {
+    /// Record a request.
+    fn new(request: &Request) -> Self {
+        Self {
+            method: request.method().to_string(),
+            uri: request.uri().to_string(),
+            body: String::from_utf8_lossy(request.body()).into_owned(),
+        }
+    }
+}
+
+/// How a recorded body is stored
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyEncoding {
+    /// The body is utf8 text
+    #[default]
+    Utf8,
+
+    /// The body is binary, stored as base64
+    Base64,
+}
+
+impl BodyEncoding {
+    /// Returns true if this is utf8.
+    fn is_utf8(&self) -> bool {
+        *self == Self::Utf8
+    }
+}
+
+/// A recorded response
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RecordedResponse {
+    /// The status code
+    pub status: u16,
+
+    /// The final url, after redirects
+    #[serde(default)]
+    pub url: Option<Url>,
+
+    /// The headers, without cookies or credentials
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+
+    /// The body, encoded as `body_encoding`
+    #[serde(default)]
+    pub body: String,
+
+    /// How the body is stored
+    #[serde(default, skip_serializing_if = "BodyEncoding::is_utf8")]
+    pub body_encoding: BodyEncoding,
+}
+
+impl RecordedResponse {
+    /// Record a response.
+    pub(crate) fn new(response: &Response) -> Self {
+        let (body, body_encoding) = match std::str::from_utf8(response.body()) {
+            Ok(body) => (body.to_string(), BodyEncoding::Utf8),
+            Err(_) => (BASE64.encode(response.body()), BodyEncoding::Base64),
+        };
+        Self {
+            status: response.status().as_u16(),
+            url: response
+                .extensions()
+                .get::<ResponseUrl>()
+                .map(|url| url.0.clone()),
+            headers: response
+                .headers()
+                .iter()
+                .filter(|(name, _)| !REDACTED_HEADERS.contains(name))
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.into())))
+                .collect(),
+            body,
+            body_encoding,
+        }
+    }
+
+    /// Turn this back into a response.
+    pub(crate) fn to_response(&self) -> Result<Response, Error> {
+        let mut builder = http::Response::builder().status(self.status);
+        for (name, value) in self.headers.iter() {
+            builder = builder.header(name, value);
+        }
+        if let Some(url) = self.url.clone() {
+            builder = builder.extension(ResponseUrl(url));
+        }
+
+        let body = match self.body_encoding {
+            BodyEncoding::Utf8 => self.body.clone().into_bytes(),
+            BodyEncoding::Base64 => BASE64.decode(&self.body).map_err(CassetteError::from)?,
+        };
+
+        Ok(builder.body(body)?)
+    }
+}
+
+/// A request and its response
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Interaction {
+    /// The request
+    pub request: RecordedRequest,
+
+    /// The response
+    pub response: RecordedResponse,
+}
+
+/// A list of recorded interactions.
+///
+/// Bodies that are not utf8, like files, are stored as base64.
+/// Cookies are never recorded, so cassettes are safe to commit.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Cassette {
+    /// The interactions, in the order they happened
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Load a cassette from a json file.
+    pub fn load<P>(path: P) -> Result<Self, CassetteError>
+    where
+        P: AsRef<Path>,
+    {
+        let text = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Save this cassette to a json file.
+    pub fn save<P>(&self, path: P) -> Result<(), CassetteError>
+    where
+        P: AsRef<Path>,
+    {
+        let text = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+/// A transport that records interactions made through another transport.
+///
+/// Call [`RecordingTransport::save`] to write the cassette.
+#[derive(Debug)]
+pub struct RecordingTransport {
+    /// The transport requests are sent through
+    inner: Arc<dyn Transport>,
+
+    /// The recorded interactions
+    cassette: Mutex<Cassette>,
+
+    /// The path to save to
+    path: PathBuf,
+}
+
+impl RecordingTransport {
+    /// Record interactions made through `inner`, to be saved to `path`.
+    pub fn new<P>(inner: Arc<dyn Transport>, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            inner,
+            cassette: Mutex::new(Cassette::default()),
+            path: path.into(),
+        }
+    }
+
+    /// Get a copy of the cassette recorded so far.
+    pub fn cassette(&self) -> Cassette {
+        self.cassette
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .clone()
+    }
+
+    /// Save the cassette recorded so far.
+    pub fn save(&self) -> Result<(), CassetteError> {
+        self.cassette().save(&self.path)
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        Box::pin(async move {
+            let recorded_request = RecordedRequest::new(&request);
+            let response = self.inner.send(request).await?;
+
+            self.cassette
+                .lock()
+                .unwrap_or_else(|error| error.into_inner())
+                .interactions
+                .push(Interaction {
+                    request: recorded_request,
+                    response: RecordedResponse::new(&response),
+                });
+
+            Ok(response)
+        })
+    }
+}
+
+/// A transport that serves responses from a cassette.
+///
+/// Requests are matched by method, uri and body.
+/// Each interaction is served once, in the order it was recorded.
+#[derive(Debug)]
+pub struct ReplayTransport {
+    /// The interactions, and whether they were served
+    interactions: Mutex<Vec<(Interaction, bool)>>,
+}
+
+impl ReplayTransport {
+    /// Replay a cassette.
+    pub fn new(cassette: Cassette) -> Self {
+        Self {
+            interactions: Mutex::new(
+                cassette
+                    .interactions
+                    .into_iter()
+                    .map(|interaction| (interaction, false))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Replay a cassette from a json file.
+    pub fn load<P>(path: P) -> Result<Self, CassetteError>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        Box::pin(async move {
+            let request = RecordedRequest::new(&request);
+            let mut interactions = self
+                .interactions
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            let (interaction, served) = interactions
+                .iter_mut()
+                .find(|(interaction, served)| !served && interaction.request == request)
+                .ok_or(CassetteError::NoMatch {
+                    method: request.method,
+                    uri: request.uri,
+                })?;
+            *served = true;
+
+            interaction.response.to_response()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recorded_response() {
+        let response = http::Response::builder()
+            .header(http::header::CONTENT_TYPE, "application/zip")
+            .header(http::header::SET_COOKIE, "itchio=secret")
+            .body(vec![0xff, 0x00, 0xfe])
+            .expect("failed to build response");
+
+        let recorded = RecordedResponse::new(&response);
+        assert_eq!(
+            recorded.headers,
+            [("content-type".to_string(), "application/zip".to_string())]
+        );
+        assert_eq!(recorded.body_encoding, BodyEncoding::Base64);
+
+        let replayed = recorded.to_response().expect("failed to replay response");
+        assert_eq!(replayed.body(), &[0xff, 0x00, 0xfe]);
+    }
+}
@@ -1,44 +1,91 @@
+/// Byte size
+pub mod byte_size;
+/// Parse diagnostics
+pub mod diagnostic;
+/// Download info
+pub mod download_info;
 /// Download page
 pub mod download_page;
 /// Game Page
 pub mod game_page;
+/// Game url
+pub mod game_url;
+/// Ids
+pub mod id;
+/// Platform
+pub mod platform;
 /// Purchase dialog
 pub mod purchase_dialog;
+/// Css selectors
+pub mod selectors;
+/// Upload
+pub mod upload;
 
+pub use self::byte_size::ByteSize;
+pub use self::byte_size::ByteUnit;
+pub use self::byte_size::ParseByteSizeError;
+pub use self::diagnostic::Diagnostic;
+pub use self::diagnostic::Parsed;
+pub use self::download_info::DownloadInfo;
+pub use self::download_info::ExternalDownload;
+pub use self::download_info::Lightbox;
 pub use self::download_page::DownloadPage;
 pub use self::game_page::GamePage;
+pub use self::game_page::PartialGamePage;
+pub use self::game_url::GameUrl;
+pub use self::game_url::ParseGameUrlError;
+pub use self::id::GameId;
+pub use self::id::UploadId;
+pub use self::platform::Platform;
 pub use self::purchase_dialog::PurchaseDialog;
+pub use self::selectors::Selectors;
+pub use self::upload::Upload;
+pub use self::upload::UploadTag;
 use url::Url;
 
-/// Download info
-#[derive(Debug, serde::Deserialize)]
-pub struct DownloadInfo {
-    /// ?
-    pub external: bool,
-
-    /// html?
-    pub lightbox: String,
-
-    /// The download url
-    pub url: Url,
-}
-
 /// The download page url
 #[derive(Debug, serde::Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct DownloadPageUrlInfo {
     /// The download page url
     pub url: Url,
 }
 
-/// A platform
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-pub enum Platform {
-    /// Windows
-    Windows,
+impl DownloadPageUrlInfo {
+    /// Parse this from a json response body.
+    pub fn from_json(body: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(body)
+    }
+}
+
+/// Upload info from the itch.io api
+#[derive(Debug, serde::Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct UploadInfo {
+    /// The file name
+    #[serde(default)]
+    pub filename: Option<String>,
+
+    /// The exact file size, in bytes
+    #[serde(default)]
+    pub size: Option<u64>,
+
+    /// The md5 hash of the file, as hex
+    #[serde(default)]
+    pub md5_hash: Option<String>,
+}
 
-    /// Linux
-    Linux,
+impl UploadInfo {
+    /// Parse this from a json response body.
+    pub fn from_json(body: &[u8]) -> Result<Self, serde_json::Error> {
+        /// The response body
+        #[derive(serde::Deserialize)]
+        struct Response {
+            upload: UploadInfo,
+        }
 
-    /// MacOs
-    MacOs,
+        Ok(serde_json::from_slice::<Response>(body)?.upload)
+    }
 }
@@ -0,0 +1,427 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+/// An error that may occur while parsing a byte size
+#[derive(Debug, thiserror::Error)]
+pub enum ParseByteSizeError {
+    /// The string is empty
+    #[error("empty byte size")]
+    Empty,
+
+    /// The value is missing
+    #[error("missing value")]
+    MissingValue,
+
+    /// The unit is missing
+    #[error("missing unit")]
+    MissingUnit,
+
+    /// The value is not a valid number
+    #[error("invalid value `{0}`")]
+    InvalidValue(String),
+
+    /// The unit is not known
+    #[error("unknown unit `{0}`")]
+    UnknownUnit(String),
+
+    /// The value is a fraction of a byte
+    #[error("fractional byte value `{0}`")]
+    FractionalBytes(String),
+
+    /// The value does not fit in a u64
+    #[error("byte size overflowed")]
+    Overflow,
+}
+
+/// A unit of bytes
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ByteUnit {
+    /// Bytes
+    Byte,
+
+    /// Kilobytes, 1000 bytes
+    Kilobyte,
+
+    /// Megabytes, 1000 kilobytes
+    Megabyte,
+
+    /// Gigabytes, 1000 megabytes
+    Gigabyte,
+
+    /// Terabytes, 1000 gigabytes
+    Terabyte,
+
+    /// Kibibytes, 1024 bytes
+    Kibibyte,
+
+    /// Mebibytes, 1024 kibibytes
+    Mebibyte,
+
+    /// Gibibytes, 1024 mebibytes
+    Gibibyte,
+
+    /// Tebibytes, 1024 gibibytes
+    Tebibyte,
+}
+
+impl ByteUnit {
+    /// Get the number of bytes in one of this unit.
+    pub fn multiplier(self) -> u64 {
+        match self {
+            Self::Byte => 1,
+            Self::Kilobyte => 1_000,
+            Self::Megabyte => 1_000_000,
+            Self::Gigabyte => 1_000_000_000,
+            Self::Terabyte => 1_000_000_000_000,
+            Self::Kibibyte => 1 << 10,
+            Self::Mebibyte => 1 << 20,
+            Self::Gibibyte => 1 << 30,
+            Self::Tebibyte => 1 << 40,
+        }
+    }
+
+    /// Get this as a str, as itch.io would display it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Byte => "bytes",
+            Self::Kilobyte => "kB",
+            Self::Megabyte => "MB",
+            Self::Gigabyte => "GB",
+            Self::Terabyte => "TB",
+            Self::Kibibyte => "KiB",
+            Self::Mebibyte => "MiB",
+            Self::Gibibyte => "GiB",
+            Self::Tebibyte => "TiB",
+        }
+    }
+}
+
+impl FromStr for ByteUnit {
+    type Err = ParseByteSizeError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "b" | "byte" | "bytes" => Ok(Self::Byte),
+            "kb" => Ok(Self::Kilobyte),
+            "mb" => Ok(Self::Megabyte),
+            "gb" => Ok(Self::Gigabyte),
+            "tb" => Ok(Self::Terabyte),
+            "kib" => Ok(Self::Kibibyte),
+            "mib" => Ok(Self::Mebibyte),
+            "gib" => Ok(Self::Gibibyte),
+            "tib" => Ok(Self::Tebibyte),
+            _ => Err(ParseByteSizeError::UnknownUnit(input.into())),
+        }
+    }
+}
+
+impl Display for ByteUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// A byte size, as displayed by itch.io.
+///
+/// This looks like `5 MB`, `1.5 GB` or `512 bytes`.
+/// itch.io only displays a few significant digits,
+/// so this keeps track of the displayed precision.
+/// Sizes are ordered by their approximate byte count,
+/// then by their precision bounds.
+#[derive(Debug, Copy, Clone)]
+pub struct ByteSize {
+    /// The displayed digits, without the decimal separator
+    mantissa: u64,
+
+    /// The number of displayed digits after the decimal separator
+    scale: u32,
+
+    /// The unit
+    unit: ByteUnit,
+}
+
+impl ByteSize {
+    /// Make a byte size from an exact number of bytes.
+    pub fn from_bytes(bytes: u64) -> Self {
+        Self {
+            mantissa: bytes,
+            scale: 0,
+            unit: ByteUnit::Byte,
+        }
+    }
+
+    /// Get the unit.
+    pub fn unit(&self) -> ByteUnit {
+        self.unit
+    }
+
+    /// The size of one step in the last displayed digit, in bytes.
+    fn step(&self) -> u128 {
+        u128::from(self.unit.multiplier()) / 10_u128.pow(self.scale)
+    }
+
+    /// Get the approximate number of bytes.
+    pub fn bytes(&self) -> u64 {
+        let bytes = u128::from(self.mantissa) * u128::from(self.unit.multiplier())
+            / 10_u128.pow(self.scale);
+        u64::try_from(bytes).unwrap_or(u64::MAX)
+    }
+
+    /// Get the lowest number of bytes this size could represent.
+    ///
+    /// This assumes itch.io rounded the displayed value to the nearest digit.
+    pub fn lower_bound(&self) -> u64 {
+        let half_step = u64::try_from(self.step() / 2).unwrap_or(u64::MAX);
+        self.bytes().saturating_sub(half_step)
+    }
+
+    /// Get the highest number of bytes this size could represent, exclusive for non-exact sizes.
+    ///
+    /// This allows for a full step above the displayed value,
+    /// in case itch.io truncated the displayed value instead of rounding.
+    pub fn upper_bound(&self) -> u64 {
+        let step = u64::try_from(self.step()).unwrap_or(u64::MAX);
+        if step <= 1 {
+            return self.bytes();
+        }
+        self.bytes().saturating_add(step)
+    }
+
+    /// Returns true if the given number of bytes is within the bounds of this size.
+    pub fn contains(&self, bytes: u64) -> bool {
+        (self.lower_bound()..=self.upper_bound()).contains(&bytes)
+    }
+
+    /// The key used for comparisons.
+    fn key(&self) -> (u64, u64, u64) {
+        (self.bytes(), self.lower_bound(), self.upper_bound())
+    }
+}
+
+/// Parse the displayed digits of a value into a mantissa and scale.
+fn parse_value(value: &str, unit: ByteUnit) -> Result<(u64, u32), ParseByteSizeError> {
+    // Drop grouping characters that are never decimal separators.
+    let value: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '\'' && *c != '\u{2019}')
+        .collect();
+
+    let last_dot = value.rfind('.');
+    let last_comma = value.rfind(',');
+    let decimal_separator = match (last_dot, last_comma) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) | (None, Some(_)) => {
+            let separator = if last_dot.is_some() { '.' } else { ',' };
+            let count = value.matches(separator).count();
+
+            // A lone separator is a decimal separator,
+            // unless it is a comma followed by exactly three digits, like `1,024`.
+            // Several of the same separator, like `1.234.567`, group thousands.
+            let groups_thousands = separator == ','
+                && value
+                    .rsplit_once(separator)
+                    .is_some_and(|(_, fraction)| fraction.len() == 3);
+            (count == 1 && !groups_thousands).then_some(separator)
+        }
+        (None, None) => None,
+    };
+
+    let (integer, fraction) = match decimal_separator.and_then(|c| value.rsplit_once(c)) {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (value.as_str(), ""),
+    };
+    let integer: String = integer.chars().filter(|c| *c != '.' && *c != ',').collect();
+
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(ParseByteSizeError::MissingValue);
+    }
+    if !integer
+        .chars()
+        .chain(fraction.chars())
+        .all(|c| c.is_ascii_digit())
+    {
+        return Err(ParseByteSizeError::InvalidValue(value));
+    }
+
+    if unit == ByteUnit::Byte && !fraction.is_empty() {
+        return Err(ParseByteSizeError::FractionalBytes(value));
+    }
+
+    let scale = u32::try_from(fraction.len()).map_err(|_| ParseByteSizeError::Overflow)?;
+    let mantissa = format!("{integer}{fraction}")
+        .parse()
+        .map_err(|_| ParseByteSizeError::Overflow)?;
+
+    Ok((mantissa, scale))
+}
+
+impl FromStr for ByteSize {
+    type Err = ParseByteSizeError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(ParseByteSizeError::Empty);
+        }
+
+        let unit_start = input
+            .find(|c: char| c.is_alphabetic())
+            .ok_or(ParseByteSizeError::MissingUnit)?;
+        let (value, unit) = input.split_at(unit_start);
+        let unit: ByteUnit = unit.trim().parse()?;
+        let (mantissa, scale) = parse_value(value, unit)?;
+
+        let bytes = u128::from(mantissa)
+            .checked_mul(u128::from(unit.multiplier()))
+            .zip(10_u128.checked_pow(scale))
+            .map(|(value, divisor)| value / divisor)
+            .ok_or(ParseByteSizeError::Overflow)?;
+        if bytes > u128::from(u64::MAX) {
+            return Err(ParseByteSizeError::Overflow);
+        }
+
+        Ok(Self {
+            mantissa,
+            scale,
+            unit,
+        })
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let divisor = 10_u64.pow(self.scale);
+        let integer = self.mantissa / divisor;
+        if self.scale == 0 {
+            write!(f, "{integer} {}", self.unit)
+        } else {
+            let fraction = self.mantissa % divisor;
+            let scale = self.scale as usize;
+            write!(f, "{integer}.{fraction:0scale$} {}", self.unit)
+        }
+    }
+}
+
+impl PartialEq for ByteSize {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for ByteSize {}
+
+impl PartialOrd for ByteSize {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ByteSize {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for ByteSize {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+serde_via_str!(ByteSize, "ByteSize", {
+    "type": "string",
+    "description": "A byte size as displayed by itch.io, like `1.5 GB`",
+});
+
+serde_via_str!(ByteUnit, "ByteUnit", {
+    "type": "string",
+    "enum": ["bytes", "kB", "MB", "GB", "TB", "KiB", "MiB", "GiB", "TiB"],
+});
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("5 MB", 5_000_000, "5 MB"),
+            ("12 kB", 12_000, "12 kB"),
+            ("1.5 GB", 1_500_000_000, "1.5 GB"),
+            ("1,5 GB", 1_500_000_000, "1.5 GB"),
+            ("512 bytes", 512, "512 bytes"),
+            ("1,024 bytes", 1024, "1024 bytes"),
+            ("1,024 kB", 1_024_000, "1024 kB"),
+            ("1.125 MB", 1_125_000, "1.125 MB"),
+            ("1.234,5 MB", 1_234_500_000, "1234.5 MB"),
+            ("1,234.5 MB", 1_234_500_000, "1234.5 MB"),
+            ("0.25\u{a0}MB", 250_000, "0.25 MB"),
+            ("3 KiB", 3072, "3 KiB"),
+        ];
+
+        for (input, bytes, display) in cases {
+            let size: ByteSize = input.parse().expect("failed to parse");
+            assert_eq!(size.bytes(), bytes, "{input}");
+            assert_eq!(size.to_string(), display, "{input}");
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(matches!(
+            "".parse::<ByteSize>(),
+            Err(ParseByteSizeError::Empty)
+        ));
+        assert!(matches!(
+            "5".parse::<ByteSize>(),
+            Err(ParseByteSizeError::MissingUnit)
+        ));
+        assert!(matches!(
+            "MB".parse::<ByteSize>(),
+            Err(ParseByteSizeError::MissingValue)
+        ));
+        assert!(matches!(
+            "5 parsecs".parse::<ByteSize>(),
+            Err(ParseByteSizeError::UnknownUnit(_))
+        ));
+        assert!(matches!(
+            "1-2 MB".parse::<ByteSize>(),
+            Err(ParseByteSizeError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            "1.5 bytes".parse::<ByteSize>(),
+            Err(ParseByteSizeError::FractionalBytes(_))
+        ));
+        assert!(matches!(
+            "1.234,5 bytes".parse::<ByteSize>(),
+            Err(ParseByteSizeError::FractionalBytes(_))
+        ));
+    }
+
+    #[test]
+    fn bounds() {
+        let size: ByteSize = "1.5 GB".parse().expect("failed to parse");
+        assert_eq!(size.lower_bound(), 1_450_000_000);
+        assert_eq!(size.upper_bound(), 1_600_000_000);
+        assert!(size.contains(1_500_000_001));
+        assert!(!size.contains(1_700_000_000));
+
+        let size: ByteSize = "512 bytes".parse().expect("failed to parse");
+        assert_eq!(size.lower_bound(), 512);
+        assert_eq!(size.upper_bound(), 512);
+    }
+
+    #[test]
+    fn ord() {
+        let small: ByteSize = "999 kB".parse().expect("failed to parse");
+        let large: ByteSize = "1 MB".parse().expect("failed to parse");
+        assert!(small < large);
+        assert_eq!(
+            "1.5 GB".parse::<ByteSize>().expect("failed to parse"),
+            "1,5 GB".parse::<ByteSize>().expect("failed to parse")
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::types::selectors::CssSelector;
+use scraper::ElementRef;
+use std::fmt::Display;
+
+/// The max number of chars kept in an html snippet
+const MAX_SNIPPET_LEN: usize = 256;
+
+/// A problem found while parsing a page.
+///
+/// In strict mode this is returned as the error.
+/// In lenient mode these are returned as warnings alongside the partial result.
+///
+/// Formatting this with `{:#}` includes the html snippet.
+/// The error is not formatted, it is returned as the source instead.
+#[derive(Debug)]
+pub struct Diagnostic<E> {
+    /// The field that failed to parse, like `title` or `uploads[2].size`
+    pub field: String,
+
+    /// The selector used to locate the field, if any
+    pub selector: Option<String>,
+
+    /// A trimmed html snippet of the offending element, if any
+    pub snippet: Option<String>,
+
+    /// The error
+    pub error: E,
+}
+
+impl<E> Diagnostic<E> {
+    /// Make a new diagnostic for a field.
+    pub(crate) fn new(field: impl Into<String>, error: E) -> Self {
+        Self {
+            field: field.into(),
+            selector: None,
+            snippet: None,
+            error,
+        }
+    }
+
+    /// Set the selector.
+    pub(crate) fn with_selector(mut self, selector: &CssSelector) -> Self {
+        self.selector = Some(selector.as_str().into());
+        self
+    }
+
+    /// Set the snippet from an element.
+    pub(crate) fn with_snippet(mut self, element: ElementRef) -> Self {
+        self.snippet = Some(snippet(element));
+        self
+    }
+
+    /// Map the error of this diagnostic.
+    pub fn map_error<F, E2>(self, f: F) -> Diagnostic<E2>
+    where
+        F: FnOnce(E) -> E2,
+    {
+        Diagnostic {
+            field: self.field,
+            selector: self.selector,
+            snippet: self.snippet,
+            error: f(self.error),
+        }
+    }
+}
+
+impl<E> Display for Diagnostic<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to parse `{}`", self.field)?;
+        if let Some(selector) = self.selector.as_deref() {
+            write!(f, " (selector `{selector}`)")?;
+        }
+
+        // The snippet is noisy, so only show it when asked for.
+        if f.alternate() {
+            if let Some(snippet) = self.snippet.as_deref() {
+                write!(f, " in `{snippet}`")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<E> std::error::Error for Diagnostic<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The result of parsing a page in lenient mode.
+#[derive(Debug)]
+pub struct Parsed<T, E> {
+    /// The parsed value
+    pub value: T,
+
+    /// Problems found while parsing.
+    ///
+    /// Fields or items that failed to parse are left out of `value`.
+    pub warnings: Vec<Diagnostic<E>>,
+}
+
+impl<T, E> Parsed<T, E> {
+    /// Returns true if nothing went wrong while parsing.
+    pub fn is_complete(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+/// Make a trimmed html snippet of an element.
+pub(crate) fn snippet(element: ElementRef) -> String {
+    let html = element.html();
+    let mut snippet = String::with_capacity(html.len().min(MAX_SNIPPET_LEN));
+    for (i, word) in html.split_whitespace().enumerate() {
+        if i != 0 {
+            snippet.push(' ');
+        }
+        snippet.push_str(word);
+    }
+
+    if let Some((end, _)) = snippet.char_indices().nth(MAX_SNIPPET_LEN) {
+        snippet.truncate(end);
+        snippet.push('…');
+    }
+
+    snippet
+}
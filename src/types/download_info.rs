@@ -0,0 +1,364 @@
+use crate::types::selectors::Selectors;
+use crate::types::selectors::DEFAULT_SELECTORS;
+use scraper::ElementRef;
+use scraper::Html;
+use url::Url;
+
+/// Download info
+#[derive(Debug, serde::Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct DownloadInfo {
+    /// Whether `url` points to a page off itch.io instead of a file.
+    ///
+    /// See [`DownloadInfo::external_download`].
+    pub external: bool,
+
+    /// The html of the lightbox shown after clicking download.
+    ///
+    /// See [`DownloadInfo::lightbox`].
+    pub lightbox: String,
+
+    /// The download url
+    pub url: Url,
+}
+
+impl DownloadInfo {
+    /// Parse this from a json response body.
+    pub fn from_json(body: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(body)
+    }
+
+    /// Parse the lightbox html.
+    pub fn lightbox(&self) -> Lightbox {
+        self.lightbox_with(&DEFAULT_SELECTORS)
+    }
+
+    /// Parse the lightbox html, using the given selectors.
+    pub fn lightbox_with(&self, selectors: &Selectors) -> Lightbox {
+        Lightbox::from_html_with(&Html::parse_fragment(&self.lightbox), selectors)
+    }
+
+    /// Classify and resolve the target of an external download.
+    ///
+    /// Returns `None` if this is not an external download.
+    pub fn external_download(&self) -> Option<ExternalDownload> {
+        self.external.then(|| ExternalDownload::resolve(&self.url))
+    }
+}
+
+/// The kind of a lightbox message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub enum LightboxMessageKind {
+    /// The thank-you text itch.io shows for every download
+    ThankYou,
+
+    /// A note the creator attached to their downloads
+    CreatorNote,
+
+    /// An error, like a download that is no longer available
+    Error,
+}
+
+/// A message in a lightbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct LightboxMessage {
+    /// The kind of message
+    pub kind: LightboxMessageKind,
+
+    /// The text, with whitespace collapsed
+    pub text: String,
+}
+
+/// The lightbox shown after clicking download
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Lightbox {
+    /// The messages, in document order within each kind
+    pub messages: Vec<LightboxMessage>,
+}
+
+impl Lightbox {
+    /// Parse this from html.
+    pub fn from_html(html: &Html) -> Self {
+        Self::from_html_with(html, &DEFAULT_SELECTORS)
+    }
+
+    /// Parse this from html, using the given selectors.
+    pub fn from_html_with(html: &Html, selectors: &Selectors) -> Self {
+        let selectors = &selectors.lightbox;
+        let messages = [
+            (LightboxMessageKind::ThankYou, &selectors.thank_you),
+            (LightboxMessageKind::CreatorNote, &selectors.creator_note),
+            (LightboxMessageKind::Error, &selectors.error),
+        ]
+        .into_iter()
+        .flat_map(|(kind, selector)| {
+            html.select(selector).filter_map(move |element| {
+                let text = element_text(element);
+                (!text.is_empty()).then_some(LightboxMessage { kind, text })
+            })
+        })
+        .collect();
+
+        Self { messages }
+    }
+
+    /// Iterate over the messages of a kind.
+    pub fn messages_of(&self, kind: LightboxMessageKind) -> impl Iterator<Item = &str> {
+        self.messages
+            .iter()
+            .filter(move |message| message.kind == kind)
+            .map(|message| message.text.as_str())
+    }
+
+    /// Returns true if the lightbox contains an error.
+    pub fn has_error(&self) -> bool {
+        self.messages_of(LightboxMessageKind::Error)
+            .next()
+            .is_some()
+    }
+}
+
+/// Get the text of an element, with whitespace collapsed.
+fn element_text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The host of an external download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub enum ExternalHost {
+    /// Dropbox
+    Dropbox,
+
+    /// Google Drive
+    GoogleDrive,
+
+    /// GitHub releases
+    GitHubReleases,
+
+    /// Any other host
+    Other,
+}
+
+impl ExternalHost {
+    /// Classify a url by its host.
+    pub fn of(url: &Url) -> Self {
+        let host = url.host_str().unwrap_or_default();
+        let path = url.path();
+        if host == "dropbox.com" || host.ends_with(".dropbox.com") {
+            Self::Dropbox
+        } else if host == "drive.google.com" {
+            Self::GoogleDrive
+        } else if host == "github.com" && path.split('/').nth(3) == Some("releases") {
+            Self::GitHubReleases
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// The target of an external download
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub enum ExternalDownload {
+    /// A url that serves the file directly
+    Direct {
+        /// The host
+        host: ExternalHost,
+
+        /// The file url
+        url: Url,
+    },
+
+    /// A page that has to be visited to download by hand
+    Manual {
+        /// The host
+        host: ExternalHost,
+
+        /// The page url
+        url: Url,
+    },
+}
+
+impl ExternalDownload {
+    /// Classify an external url and resolve it into a direct download url, if possible.
+    ///
+    /// * Dropbox share links are resolved by setting `dl=1`.
+    /// * Google Drive file links are resolved to the `uc?export=download` endpoint.
+    ///   Folders are manual.
+    /// * GitHub release asset links are direct.
+    ///   Release pages are manual, as picking an asset needs the GitHub api.
+    /// * Everything else is manual.
+    pub fn resolve(url: &Url) -> Self {
+        let host = ExternalHost::of(url);
+        let direct = match host {
+            ExternalHost::Dropbox => resolve_dropbox(url),
+            ExternalHost::GoogleDrive => resolve_google_drive(url),
+            ExternalHost::GitHubReleases => url
+                .path()
+                .split('/')
+                .nth(4)
+                .filter(|segment| *segment == "download")
+                .map(|_| url.clone()),
+            ExternalHost::Other => None,
+        };
+
+        match direct {
+            Some(url) => Self::Direct { host, url },
+            None => Self::Manual {
+                host,
+                url: url.clone(),
+            },
+        }
+    }
+
+    /// Get the host.
+    pub fn host(&self) -> ExternalHost {
+        match self {
+            Self::Direct { host, .. } | Self::Manual { host, .. } => *host,
+        }
+    }
+
+    /// Get the url.
+    pub fn url(&self) -> &Url {
+        match self {
+            Self::Direct { url, .. } | Self::Manual { url, .. } => url,
+        }
+    }
+
+    /// Returns true if the url serves the file directly.
+    pub fn is_direct(&self) -> bool {
+        matches!(self, Self::Direct { .. })
+    }
+}
+
+/// Resolve a Dropbox share link.
+///
+/// Folder links are not resolved, as they serve a zip that may not be what the creator meant.
+fn resolve_dropbox(url: &Url) -> Option<Url> {
+    if url.path().starts_with("/sh/") || url.path().starts_with("/scl/fo/") {
+        return None;
+    }
+
+    let mut resolved = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "dl")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    resolved
+        .query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("dl", "1");
+    Some(resolved)
+}
+
+/// Resolve a Google Drive file link, like `/file/d/{id}/view` or `/open?id={id}`.
+fn resolve_google_drive(url: &Url) -> Option<Url> {
+    let mut segments = url.path_segments()?;
+    let id = match (segments.next(), segments.next(), segments.next()) {
+        (Some("file"), Some("d"), Some(id)) => id.to_string(),
+        (Some("open" | "uc"), _, _) => url
+            .query_pairs()
+            .find(|(key, _)| key == "id")
+            .map(|(_, id)| id.into_owned())?,
+        _ => return None,
+    };
+
+    let mut resolved = Url::parse("https://drive.google.com/uc?export=download").ok()?;
+    resolved.query_pairs_mut().append_pair("id", &id);
+    Some(resolved)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lightbox() {
+        let info = DownloadInfo {
+            external: false,
+            lightbox: r#"
+                <div class="download_lightbox">
+                    <h2>Thanks for
+                        downloading!</h2>
+                    <div class="formatted_message user_formatted">
+                        <p>Join the <a href="https://example.com">discord</a>.</p>
+                    </div>
+                </div>
+            "#
+            .into(),
+            url: Url::parse("https://files.example.com/game.zip").expect("failed to parse url"),
+        };
+        let lightbox = info.lightbox();
+        assert_eq!(
+            lightbox
+                .messages_of(LightboxMessageKind::ThankYou)
+                .collect::<Vec<_>>(),
+            ["Thanks for downloading!"]
+        );
+        assert_eq!(
+            lightbox
+                .messages_of(LightboxMessageKind::CreatorNote)
+                .collect::<Vec<_>>(),
+            ["Join the discord."]
+        );
+        assert!(!lightbox.has_error());
+        assert!(info.external_download().is_none());
+    }
+
+    #[test]
+    fn external() {
+        let cases = [
+            (
+                "https://www.dropbox.com/s/abc/game.zip?dl=0",
+                Some("https://www.dropbox.com/s/abc/game.zip?dl=1"),
+            ),
+            (
+                "https://drive.google.com/file/d/FILE_ID/view?usp=sharing",
+                Some("https://drive.google.com/uc?export=download&id=FILE_ID"),
+            ),
+            ("https://drive.google.com/drive/folders/FOLDER_ID", None),
+            (
+                "https://github.com/owner/repo/releases/download/v1.0/game.zip",
+                Some("https://github.com/owner/repo/releases/download/v1.0/game.zip"),
+            ),
+            ("https://github.com/owner/repo/releases/latest", None),
+            ("https://example.com/game", None),
+        ];
+        for (url, expected) in cases {
+            let download =
+                ExternalDownload::resolve(&Url::parse(url).expect("failed to parse url"));
+            match expected {
+                Some(expected) => {
+                    assert!(download.is_direct(), "{url}");
+                    assert_eq!(download.url().as_str(), expected);
+                }
+                None => {
+                    assert!(!download.is_direct(), "{url}");
+                    assert_eq!(download.url().as_str(), url);
+                }
+            }
+        }
+    }
+}
@@ -1,170 +1,99 @@
+use crate::types::selectors::Selectors;
+use crate::types::selectors::DEFAULT_SELECTORS;
+use crate::types::upload::best_upload_for;
+use crate::types::upload::parse_uploads;
+use crate::types::upload::FromElementError;
+use crate::types::Diagnostic;
+use crate::types::Parsed;
 use crate::types::Platform;
-use once_cell::sync::Lazy;
-use scraper::ElementRef;
+use crate::types::Upload;
 use scraper::Html;
-use scraper::Selector;
-
-static DOWNLOAD_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".upload").expect("invalid DOWNLOAD_SELECTOR"));
-static TITLE_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".name").expect("invalid TITLE_SELECTOR"));
-static FILE_SIZE_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".file_size > span").expect("invalid FILE_SIZE_SELECTOR"));
-static DOWNLOAD_BTN_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".download_btn").expect("invalid DOWNLOAD_BTN_SELECTOR"));
-static PLATFORMS_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".download_platforms").expect("invalid PLATFORM_SELECTOR"));
-static ICON_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse("span.icon").expect("invalid ICON_SELECTOR"));
 
 /// An error that occurs while parsing from html
 #[derive(Debug, thiserror::Error)]
 pub enum FromHtmlError {
-    #[error("invalid download")]
-    InvalidDownload(#[from] FromElementError),
+    #[error("invalid upload")]
+    InvalidUpload(#[from] FromElementError),
+}
+
+impl FromHtmlError {
+    /// Get the name of this error variant, like `InvalidUpload`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::InvalidUpload(_) => "InvalidUpload",
+        }
+    }
 }
 
 /// A page of downloads
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct DownloadPage {
-    /// Downloads
-    pub downloads: Vec<Download>,
+    /// Uploads.
+    ///
+    /// Uploads on the download page always have an id.
+    pub uploads: Vec<Upload>,
 }
 
 impl DownloadPage {
-    /// Parse this from html
-    pub(crate) fn from_html(html: &Html) -> Result<Self, FromHtmlError> {
-        let downloads = html
-            .select(&DOWNLOAD_SELECTOR)
-            .map(Download::from_element)
-            .collect::<Result<_, _>>()?;
-
-        Ok(Self { downloads })
+    /// Parse this from html.
+    ///
+    /// This fails on the first upload that fails to parse.
+    pub fn from_html(html: &Html) -> Result<Self, Diagnostic<FromHtmlError>> {
+        Self::from_html_with(html, &DEFAULT_SELECTORS)
     }
-}
 
-/// An error that may occur while parsing an element.
-#[derive(Debug, thiserror::Error)]
-pub enum FromElementError {
-    /// Missing Title
-    #[error("missing title")]
-    MissingTitle,
-
-    /// Missing file size
-    #[error("missing file size")]
-    MissingFileSize,
-
-    /// Missing id
-    #[error("missing id")]
-    MissingId,
-
-    /// Invalid id
-    #[error("invalid id")]
-    InvalidId(#[source] std::num::ParseIntError),
-
-    /// Missing platforms
-    #[error("missing platforms")]
-    MissingPlatforms,
-
-    /// Missing a platform string
-    #[error("missing platform string")]
-    MissingPlatformString,
-
-    /// Invalid platform
-    #[error("invalid platform string `{0}`")]
-    InvalidPlatformString(String),
-}
-
-/// A download
-#[derive(Debug)]
-pub struct Download {
-    /// The title of the download
-    pub title: String,
-
-    /// Download size
+    /// Parse this from html, using the given selectors.
     ///
-    /// This is a string representation.
-    /// The format is something like `N {specifier}`
-    /// where `specifier` is 'MB' or 'kB'.
-    pub size: String,
-
-    /// Download id
-    pub id: u64,
-
-    // The platforms this download is for
-    pub platforms: Vec<Platform>,
-}
-
-impl Download {
-    /// Make this from an element
-    fn from_element(element: ElementRef) -> Result<Self, FromElementError> {
-        let title = element
-            .select(&TITLE_SELECTOR)
-            .next()
-            .and_then(|element| element.text().next())
-            .ok_or(FromElementError::MissingTitle)?
-            .to_string();
-
-        let size = element
-            .select(&FILE_SIZE_SELECTOR)
-            .next()
-            .and_then(|element| element.text().next())
-            .ok_or(FromElementError::MissingFileSize)?
-            .to_string();
-
-        let id = element
-            .select(&DOWNLOAD_BTN_SELECTOR)
-            .next()
-            .and_then(|element| element.value().attr("data-upload_id"))
-            .ok_or(FromElementError::MissingId)?
-            .parse()
-            .map_err(FromElementError::InvalidId)?;
-
-        let platforms = element
-            .select(&PLATFORMS_SELECTOR)
-            .next()
-            .ok_or(FromElementError::MissingPlatforms)?
-            .select(&ICON_SELECTOR)
-            .map(|icon_el| {
-                let platform_str = icon_el
-                    .value()
-                    .classes()
-                    .find_map(|class| class.strip_prefix("icon-"))
-                    .ok_or(FromElementError::MissingPlatformString)?;
+    /// This fails on the first upload that fails to parse.
+    pub fn from_html_with(
+        html: &Html,
+        selectors: &Selectors,
+    ) -> Result<Self, Diagnostic<FromHtmlError>> {
+        let Parsed { value, warnings } = Self::from_html_lenient_with(html, selectors);
+        match warnings.into_iter().next() {
+            Some(warning) => Err(warning),
+            None => Ok(value),
+        }
+    }
 
-                match platform_str {
-                    "windows8" => Ok(Platform::Windows),
-                    "tux" => Ok(Platform::Linux),
-                    "apple" => Ok(Platform::MacOs),
-                    _ => Err(FromElementError::InvalidPlatformString(platform_str.into())),
-                }
-            })
-            .collect::<Result<_, _>>()?;
+    /// Parse this from html in lenient mode.
+    ///
+    /// Uploads that fail to parse are skipped, and a warning is recorded for each.
+    pub fn from_html_lenient(html: &Html) -> Parsed<Self, FromHtmlError> {
+        Self::from_html_lenient_with(html, &DEFAULT_SELECTORS)
+    }
 
-        Ok(Self {
-            title,
-            size,
-            id,
-            platforms,
-        })
+    /// Parse this from html in lenient mode, using the given selectors.
+    pub fn from_html_lenient_with(
+        html: &Html,
+        selectors: &Selectors,
+    ) -> Parsed<Self, FromHtmlError> {
+        let mut warnings = Vec::new();
+        let uploads = parse_uploads(
+            html.select(&selectors.download_page.upload),
+            &selectors.upload,
+            true,
+            &mut warnings,
+        );
+
+        Parsed {
+            value: Self { uploads },
+            warnings,
+        }
     }
 
-    /// Attempt to parse the size of this download.
-    ///
-    /// This is a rough approximation,
-    /// and usually a lower limit as the value has little precision.
+    /// Pick the best upload for a platform.
     ///
-    /// # Return
-    /// * Returns the value in bytes, otherwise None.
-    pub fn parse_size(&self) -> Option<u64> {
-        let (value, modifier) = self.size.split_once(' ')?;
-        let value: u64 = value.parse().ok()?;
-        let modifier = match modifier {
-            "MB" => 1_000_000,
-            "kB" => 1_000,
-            _ => return None,
-        };
+    /// This prefers full releases over demos, soundtracks, preorders and external uploads,
+    /// and uploads made for the given platform alone over uploads that bundle multiple platforms.
+    pub fn best_upload_for(&self, platform: &Platform) -> Option<&Upload> {
+        best_upload_for(&self.uploads, platform)
+    }
 
-        Some(value * modifier)
+    /// Pick the best upload for the host platform.
+    pub fn best_upload_for_host(&self) -> Option<&Upload> {
+        self.best_upload_for(&Platform::current()?)
     }
 }
@@ -1,36 +1,20 @@
+use crate::types::game_url::creator_from_profile_url;
+use crate::types::selectors::GamePageSelectors;
+use crate::types::selectors::Selectors;
+use crate::types::selectors::DEFAULT_SELECTORS;
+use crate::types::upload::best_upload_for;
+use crate::types::upload::parse_uploads;
+use crate::types::upload::FromElementError;
+use crate::types::Diagnostic;
+use crate::types::GameId;
+use crate::types::GameUrl;
+use crate::types::ParseGameUrlError;
+use crate::types::Parsed;
 use crate::types::Platform;
-use once_cell::sync::Lazy;
-use scraper::ElementRef;
+use crate::types::Upload;
 use scraper::Html;
-use scraper::Selector;
 use url::Url;
 
-static GAME_TITLE_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".game_title").expect("invalid GAME_TITLE_SELECTOR"));
-static TWITTER_URL_SELECTOR: Lazy<Selector> = Lazy::new(|| {
-    Selector::parse("meta[name=\"twitter:url\"]").expect("invalid TWITTER_URL_SELECTOR")
-});
-static CSRF_TOKEN_SELECTOR: Lazy<Selector> = Lazy::new(|| {
-    Selector::parse("meta[name=\"csrf_token\"]").expect("invalid CSRF_TOKEN_SELECTOR")
-});
-static DOWNLOAD_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".upload").expect("invalid DOWNLOAD_SELECTOR"));
-static DOWNLOAD_BTN_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".download_btn").expect("invalid DOWNLOAD_BTN_SELECTOR"));
-static TITLE_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".name").expect("invalid TITLE_SELECTOR"));
-static FILE_SIZE_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".file_size > span").expect("invalid FILE_SIZE_SELECTOR"));
-static PLATFORMS_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".download_platforms").expect("invalid PLATFORM_SELECTOR"));
-static ICON_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse("span.icon").expect("invalid ICON_SELECTOR"));
-static VIEW_HTML_SELECTOR: Lazy<Selector> = Lazy::new(|| {
-    Selector::parse(".view_html_game_page .iframe_placeholder").expect("invalid VIEW_HTML_SELECTOR")
-});
-static IFRAME_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse("iframe").expect("invalid IFRAME_SELECTOR"));
-
 ///  Error that may occur while parsing a game page
 #[derive(Debug, thiserror::Error)]
 pub enum FromHtmlError {
@@ -41,7 +25,10 @@ pub enum FromHtmlError {
     MissingTwitterUrl,
 
     #[error("invalid twitter url")]
-    InvalidTwitterUrl(#[source] url::ParseError),
+    InvalidTwitterUrl(#[source] ParseGameUrlError),
+
+    #[error("invalid game id")]
+    InvalidGameId(#[source] std::num::ParseIntError),
 
     #[error("missing csrf token")]
     MissingCsrfToken,
@@ -49,8 +36,8 @@ pub enum FromHtmlError {
     #[error("missing download")]
     MissingDownload,
 
-    #[error("invalid download")]
-    InvalidDownload(#[from] FromElementError),
+    #[error("invalid upload")]
+    InvalidUpload(#[from] FromElementError),
 
     #[error("missing iframe data")]
     MissingIFrameData,
@@ -62,8 +49,42 @@ pub enum FromHtmlError {
     InvalidIFrameDataSrc(#[source] url::ParseError),
 }
 
+impl FromHtmlError {
+    /// Get the name of the game page field that failed to parse.
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::MissingTitle => "title",
+            Self::MissingTwitterUrl | Self::InvalidTwitterUrl(_) => "twitter_url",
+            Self::InvalidGameId(_) => "id",
+            Self::MissingCsrfToken => "csrf_token",
+            Self::MissingDownload | Self::InvalidUpload(_) => "uploads",
+            Self::MissingIFrameData
+            | Self::MissingIFrameDataSrc
+            | Self::InvalidIFrameDataSrc(_) => "view_html_url",
+        }
+    }
+
+    /// Get the name of this error variant, like `MissingTitle`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MissingTitle => "MissingTitle",
+            Self::MissingTwitterUrl => "MissingTwitterUrl",
+            Self::InvalidTwitterUrl(_) => "InvalidTwitterUrl",
+            Self::InvalidGameId(_) => "InvalidGameId",
+            Self::MissingCsrfToken => "MissingCsrfToken",
+            Self::MissingDownload => "MissingDownload",
+            Self::InvalidUpload(_) => "InvalidUpload",
+            Self::MissingIFrameData => "MissingIFrameData",
+            Self::MissingIFrameDataSrc => "MissingIFrameDataSrc",
+            Self::InvalidIFrameDataSrc(_) => "InvalidIFrameDataSrc",
+        }
+    }
+}
+
 /// The page for a game
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct GamePage {
     /// The title of this game
     pub title: String,
@@ -71,195 +92,405 @@ pub struct GamePage {
     /// The url of this page.
     ///
     /// This is called `twitter_url` as it is scraped from twitter metadata on the page.
-    pub twitter_url: Url,
+    pub twitter_url: GameUrl,
+
+    /// The canonical url of this page, in the `creator.itch.io/slug` form.
+    ///
+    /// This is the same as `twitter_url`, unless this game is hosted on a custom domain.
+    /// If the creator of a game on a custom domain could not be determined,
+    /// this is also the same as `twitter_url`.
+    pub canonical_url: GameUrl,
+
+    /// The id of this game, if it exists
+    pub id: Option<GameId>,
 
     /// A csrf token
     pub csrf_token: String,
 
-    /// Download ids
-    pub downloads: Vec<Download>,
+    /// Uploads
+    pub uploads: Vec<Upload>,
 
     /// The view html url
     pub view_html_url: Option<Url>,
 }
 
-impl GamePage {
-    /// Parse a game page
-    pub fn from_html(html: &Html) -> Result<Self, FromHtmlError> {
-        let title = html
-            .select(&GAME_TITLE_SELECTOR)
-            .next()
-            .and_then(|title_el| title_el.text().next())
-            .ok_or(FromHtmlError::MissingTitle)?
-            .to_string();
-
-        let twitter_url = Url::parse(
-            html.select(&TWITTER_URL_SELECTOR)
-                .next()
-                .and_then(|element| element.value().attr("content"))
-                .ok_or(FromHtmlError::MissingTwitterUrl)?,
-        )
-        .map_err(FromHtmlError::InvalidTwitterUrl)?;
-
-        let csrf_token = html
-            .select(&CSRF_TOKEN_SELECTOR)
-            .next()
-            .and_then(|element| element.value().attr("value"))
-            .ok_or(FromHtmlError::MissingCsrfToken)?
-            .to_string();
-
-        let downloads = html
-            .select(&DOWNLOAD_SELECTOR)
-            .map(Download::from_element)
-            .collect::<Result<_, _>>()?;
-
-        let view_html_url = html
-            .select(&VIEW_HTML_SELECTOR)
-            .next()
-            .map(|view_html_el| {
-                let iframe_data = view_html_el
-                    .value()
-                    .attr("data-iframe")
-                    .ok_or(FromHtmlError::MissingIFrameData)?;
-                let html = Html::parse_fragment(iframe_data);
-                html.select(&IFRAME_SELECTOR)
-                    .next()
-                    .ok_or(FromHtmlError::MissingIFrameData)?
-                    .value()
-                    .attr("src")
-                    .ok_or(FromHtmlError::MissingIFrameDataSrc)
-                    .map(Url::parse)?
-                    .map_err(FromHtmlError::InvalidIFrameDataSrc)
-            })
-            .transpose()?;
+/// A game page parsed in lenient mode.
+///
+/// Fields that failed to parse are `None`.
+#[derive(Debug)]
+pub struct PartialGamePage {
+    /// The title of this game
+    pub title: Option<String>,
+
+    /// The url of this page.
+    pub twitter_url: Option<GameUrl>,
+
+    /// The canonical url of this page, in the `creator.itch.io/slug` form.
+    pub canonical_url: Option<GameUrl>,
+
+    /// The id of this game
+    pub id: Option<GameId>,
+
+    /// A csrf token
+    pub csrf_token: Option<String>,
+
+    /// Uploads that parsed successfully
+    pub uploads: Vec<Upload>,
+
+    /// The view html url
+    pub view_html_url: Option<Url>,
+}
+
+impl TryFrom<PartialGamePage> for GamePage {
+    type Error = FromHtmlError;
+
+    fn try_from(page: PartialGamePage) -> Result<Self, Self::Error> {
+        let title = page.title.ok_or(FromHtmlError::MissingTitle)?;
+        let twitter_url = page.twitter_url.ok_or(FromHtmlError::MissingTwitterUrl)?;
+        let canonical_url = page.canonical_url.unwrap_or_else(|| twitter_url.clone());
+        let csrf_token = page.csrf_token.ok_or(FromHtmlError::MissingCsrfToken)?;
 
         Ok(Self {
             title,
             twitter_url,
+            canonical_url,
+            id: page.id,
             csrf_token,
-            downloads,
-            view_html_url,
+            uploads: page.uploads,
+            view_html_url: page.view_html_url,
         })
     }
 }
 
-/// Error that may occur while parsing a download
-#[derive(Debug, thiserror::Error)]
-pub enum FromElementError {
-    /// Missing Title
-    #[error("missing title")]
-    MissingTitle,
+impl GamePage {
+    /// Parse a game page
+    ///
+    /// This fails on the first field that fails to parse.
+    pub fn from_html(html: &Html) -> Result<Self, Diagnostic<FromHtmlError>> {
+        Self::from_html_with(html, &DEFAULT_SELECTORS)
+    }
 
-    /// Missing file size
-    #[error("missing file size")]
-    MissingFileSize,
+    /// Parse a game page, using the given selectors.
+    ///
+    /// This fails on the first field that fails to parse.
+    pub fn from_html_with(
+        html: &Html,
+        selectors: &Selectors,
+    ) -> Result<Self, Diagnostic<FromHtmlError>> {
+        let Parsed { value, warnings } = Self::from_html_lenient_with(html, selectors);
+        if let Some(warning) = warnings.into_iter().next() {
+            return Err(warning);
+        }
+
+        value
+            .try_into()
+            .map_err(|error: FromHtmlError| Diagnostic::new(error.field(), error))
+    }
 
-    /// Invalid id
-    #[error("invalid id")]
-    InvalidId(#[source] std::num::ParseIntError),
+    /// Parse a game page in lenient mode.
+    ///
+    /// Fields that fail to parse are left empty and uploads that fail to parse are skipped.
+    /// A warning is recorded for each.
+    pub fn from_html_lenient(html: &Html) -> Parsed<PartialGamePage, FromHtmlError> {
+        Self::from_html_lenient_with(html, &DEFAULT_SELECTORS)
+    }
 
-    /// Missing platforms
-    #[error("missing platforms")]
-    MissingPlatforms,
+    /// Parse a game page in lenient mode, using the given selectors.
+    pub fn from_html_lenient_with(
+        html: &Html,
+        selectors: &Selectors,
+    ) -> Parsed<PartialGamePage, FromHtmlError> {
+        let page_selectors = &selectors.game_page;
+        let mut warnings = Vec::new();
+
+        let title = parse_title(html, page_selectors)
+            .map_err(|warning| warnings.push(warning))
+            .ok();
+
+        let twitter_url = parse_twitter_url(html, page_selectors)
+            .map_err(|warning| warnings.push(warning))
+            .ok();
+
+        let canonical_url = twitter_url.as_ref().map(|twitter_url| {
+            if twitter_url.is_custom_domain() {
+                find_canonical_url(html, page_selectors, twitter_url)
+                    .unwrap_or_else(|| twitter_url.clone())
+            } else {
+                twitter_url.clone()
+            }
+        });
+
+        let id = parse_id(html, page_selectors)
+            .map_err(|warning| warnings.push(warning))
+            .ok()
+            .flatten();
+
+        let csrf_token = parse_csrf_token(html, page_selectors)
+            .map_err(|warning| warnings.push(warning))
+            .ok();
+
+        let uploads = parse_uploads(
+            html.select(&page_selectors.upload),
+            &selectors.upload,
+            false,
+            &mut warnings,
+        );
+
+        let view_html_url = parse_view_html_url(html, page_selectors)
+            .map_err(|warning| warnings.push(warning))
+            .ok()
+            .flatten();
+
+        Parsed {
+            value: PartialGamePage {
+                title,
+                twitter_url,
+                canonical_url,
+                id,
+                csrf_token,
+                uploads,
+                view_html_url,
+            },
+            warnings,
+        }
+    }
 
-    /// Missing a platform string
-    #[error("missing platform string")]
-    MissingPlatformString,
+    /// Pick the best upload for a platform.
+    ///
+    /// This prefers full releases over demos, soundtracks, preorders and external uploads,
+    /// and uploads made for the given platform alone over uploads that bundle multiple platforms.
+    pub fn best_upload_for(&self, platform: &Platform) -> Option<&Upload> {
+        best_upload_for(&self.uploads, platform)
+    }
 
-    /// Invalid platform
-    #[error("invalid platform string `{0}`")]
-    InvalidPlatformString(String),
+    /// Pick the best upload for the host platform.
+    pub fn best_upload_for_host(&self) -> Option<&Upload> {
+        self.best_upload_for(&Platform::current()?)
+    }
 }
 
-/// A download
-#[derive(Debug)]
-pub struct Download {
-    /// Download title
-    pub title: String,
+/// Parse the title of a game page.
+fn parse_title(
+    html: &Html,
+    selectors: &GamePageSelectors,
+) -> Result<String, Diagnostic<FromHtmlError>> {
+    html.select(&selectors.title)
+        .next()
+        .and_then(|title_el| title_el.text().next())
+        .map(String::from)
+        .ok_or_else(|| {
+            Diagnostic::new("title", FromHtmlError::MissingTitle).with_selector(&selectors.title)
+        })
+}
 
-    /// Download size
-    ///
-    /// This is a string representation.
-    /// The format is something like `N {specifier}`
-    /// where `specifier` is 'MB' or 'kB'.
-    pub size: String,
+/// Parse the twitter url of a game page.
+fn parse_twitter_url(
+    html: &Html,
+    selectors: &GamePageSelectors,
+) -> Result<GameUrl, Diagnostic<FromHtmlError>> {
+    let diagnostic =
+        |error| Diagnostic::new("twitter_url", error).with_selector(&selectors.twitter_url);
+
+    let element = html
+        .select(&selectors.twitter_url)
+        .next()
+        .ok_or_else(|| diagnostic(FromHtmlError::MissingTwitterUrl))?;
+    let content = element
+        .value()
+        .attr("content")
+        .ok_or_else(|| diagnostic(FromHtmlError::MissingTwitterUrl).with_snippet(element))?;
+
+    GameUrl::parse(content)
+        .map_err(|error| diagnostic(FromHtmlError::InvalidTwitterUrl(error)).with_snippet(element))
+}
 
-    /// Download id, if it exists
-    pub id: Option<u64>,
+/// Parse the game id of a game page.
+fn parse_id(
+    html: &Html,
+    selectors: &GamePageSelectors,
+) -> Result<Option<GameId>, Diagnostic<FromHtmlError>> {
+    let element = match html.select(&selectors.game_path).next() {
+        Some(element) => element,
+        None => return Ok(None),
+    };
+
+    // This looks like `games/{id}`.
+    element
+        .value()
+        .attr("content")
+        .and_then(|path| path.strip_prefix("games/"))
+        .map(|id| {
+            id.parse().map_err(|error| {
+                Diagnostic::new("id", FromHtmlError::InvalidGameId(error))
+                    .with_selector(&selectors.game_path)
+                    .with_snippet(element)
+            })
+        })
+        .transpose()
+}
 
-    /// The platforms this download is for
-    pub platforms: Vec<Platform>,
+/// Parse the csrf token of a game page.
+fn parse_csrf_token(
+    html: &Html,
+    selectors: &GamePageSelectors,
+) -> Result<String, Diagnostic<FromHtmlError>> {
+    let diagnostic = || {
+        Diagnostic::new("csrf_token", FromHtmlError::MissingCsrfToken)
+            .with_selector(&selectors.csrf_token)
+    };
+
+    let element = html
+        .select(&selectors.csrf_token)
+        .next()
+        .ok_or_else(diagnostic)?;
+
+    element
+        .value()
+        .attr("value")
+        .map(String::from)
+        .ok_or_else(|| diagnostic().with_snippet(element))
 }
 
-impl Download {
-    /// Parse a download from an elemnt
-    fn from_element(element: ElementRef) -> Result<Self, FromElementError> {
-        let title = element
-            .select(&TITLE_SELECTOR)
-            .next()
-            .and_then(|element| element.text().next())
-            .ok_or(FromElementError::MissingTitle)?
-            .to_string();
-
-        let size = element
-            .select(&FILE_SIZE_SELECTOR)
-            .next()
-            .and_then(|element| element.text().next())
-            .ok_or(FromElementError::MissingFileSize)?
-            .to_string();
-
-        let id = element
-            .select(&DOWNLOAD_BTN_SELECTOR)
-            .next()
-            .and_then(|element| element.value().attr("data-upload_id"))
-            .map(|id_str| id_str.parse().map_err(FromElementError::InvalidId))
-            .transpose()?;
-
-        let platforms = element
-            .select(&PLATFORMS_SELECTOR)
-            .next()
-            .ok_or(FromElementError::MissingPlatforms)?
-            .select(&ICON_SELECTOR)
-            .map(|icon_el| {
-                let platform_str = icon_el
-                    .value()
-                    .classes()
-                    .find_map(|class| class.strip_prefix("icon-"))
-                    .ok_or(FromElementError::MissingPlatformString)?;
-
-                match platform_str {
-                    "windows8" => Ok(Platform::Windows),
-                    "tux" => Ok(Platform::Linux),
-                    "apple" => Ok(Platform::MacOs),
-                    _ => Err(FromElementError::InvalidPlatformString(platform_str.into())),
-                }
-            })
-            .collect::<Result<_, _>>()?;
+/// Parse the view html url of a game page.
+fn parse_view_html_url(
+    html: &Html,
+    selectors: &GamePageSelectors,
+) -> Result<Option<Url>, Diagnostic<FromHtmlError>> {
+    let view_html_el = match html.select(&selectors.view_html).next() {
+        Some(element) => element,
+        None => return Ok(None),
+    };
+    let diagnostic = |error| {
+        Diagnostic::new("view_html_url", error)
+            .with_selector(&selectors.view_html)
+            .with_snippet(view_html_el)
+    };
+
+    let iframe_data = view_html_el
+        .value()
+        .attr("data-iframe")
+        .ok_or_else(|| diagnostic(FromHtmlError::MissingIFrameData))?;
+    let html = Html::parse_fragment(iframe_data);
+    html.select(&selectors.iframe)
+        .next()
+        .ok_or_else(|| diagnostic(FromHtmlError::MissingIFrameData))?
+        .value()
+        .attr("src")
+        .ok_or_else(|| diagnostic(FromHtmlError::MissingIFrameDataSrc))
+        .map(Url::parse)?
+        .map(Some)
+        .map_err(|error| diagnostic(FromHtmlError::InvalidIFrameDataSrc(error)))
+}
 
-        Ok(Self {
-            title,
-            size,
-            id,
-            platforms,
+/// Try to find the itch.io url for a game on a custom domain.
+fn find_canonical_url(
+    html: &Html,
+    selectors: &GamePageSelectors,
+    twitter_url: &GameUrl,
+) -> Option<GameUrl> {
+    // The oembed link contains the itch.io url of the game as a query parameter.
+    let oembed_url = html
+        .select(&selectors.oembed)
+        .filter_map(|element| element.value().attr("href"))
+        .filter_map(|href| Url::parse(href).ok())
+        .find_map(|url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "url")
+                .and_then(|(_, value)| GameUrl::parse(&value).ok())
         })
+        .filter(|url| !url.is_custom_domain());
+    if oembed_url.is_some() {
+        return oembed_url;
     }
 
-    /// Attempt to parse the size of this download.
-    ///
-    /// This is a rough approximation,
-    /// and usually a lower limit as the value has little precision.
-    ///
-    /// # Return
-    /// * Returns the value in bytes, otherwise None.
-    pub fn parse_size(&self) -> Option<u64> {
-        let (value, modifier) = self.size.split_once(' ')?;
-        let value: u64 = value.parse().ok()?;
-        let modifier = match modifier {
-            "MB" => 1_000_000,
-            "kB" => 1_000,
-            _ => return None,
-        };
-
-        Some(value * modifier)
+    // Otherwise, try to combine the author's profile link with the slug.
+    let creator = html
+        .select(&selectors.author_link)
+        .filter_map(|element| element.value().attr("href"))
+        .filter_map(|href| Url::parse(href).ok())
+        .find_map(|url| creator_from_profile_url(&url).map(String::from))?;
+
+    twitter_url.with_creator(&creator).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn custom_domain_canonical_url() {
+        let html = Html::parse_document(
+            r#"<html>
+<head>
+    <meta name="twitter:url" content="https://games.example.com/doghouse-2/">
+    <meta name="csrf_token" value="token">
+</head>
+<body>
+    <h1 class="game_title">Doghouse 2</h1>
+    <div class="game_info_panel_widget">
+        <table><tr><td>Author</td><td><a href="https://tumblewed.itch.io/">tumblewed</a></td></tr></table>
+    </div>
+</body>
+</html>"#,
+        );
+        let game_page = GamePage::from_html(&html).expect("failed to parse");
+        assert_eq!(
+            game_page.twitter_url.as_str(),
+            "https://games.example.com/doghouse-2"
+        );
+        assert_eq!(
+            game_page.canonical_url.as_str(),
+            "https://tumblewed.itch.io/doghouse-2"
+        );
+    }
+
+    #[test]
+    fn lenient() {
+        let html = Html::parse_document(
+            r#"<html>
+<head>
+    <meta name="twitter:url" content="https://tumblewed.itch.io/doghouse-2">
+</head>
+<body>
+    <div class="upload">
+        <strong class="name">doghouse.zip</strong>
+        <span class="file_size"><span>5 MB</span></span>
+        <span class="download_platforms"><span class="icon icon-windows8"></span></span>
+    </div>
+    <div class="upload">
+        <strong class="name">broken.zip</strong>
+        <span class="file_size"><span>lots</span></span>
+        <span class="download_platforms"></span>
+    </div>
+</body>
+</html>"#,
+        );
+
+        let parsed = GamePage::from_html_lenient(&html);
+        assert!(!parsed.is_complete());
+        assert_eq!(parsed.value.title, None);
+        assert_eq!(parsed.value.csrf_token, None);
+        assert_eq!(parsed.value.uploads.len(), 1);
+        assert_eq!(parsed.value.uploads[0].title, "doghouse.zip");
+
+        let fields: Vec<&str> = parsed
+            .warnings
+            .iter()
+            .map(|warning| warning.field.as_str())
+            .collect();
+        assert_eq!(fields, ["title", "csrf_token", "uploads[1].size"]);
+        assert_eq!(
+            parsed.warnings[2].selector.as_deref(),
+            Some(".file_size > span")
+        );
+        assert!(parsed.warnings[2]
+            .snippet
+            .as_deref()
+            .is_some_and(|snippet| snippet.contains("broken.zip")));
+
+        let error = GamePage::from_html(&html).expect_err("parsed invalid page");
+        assert_eq!(error.field, "title");
+        assert_eq!(error.selector.as_deref(), Some(".game_title"));
+        assert!(matches!(error.error, FromHtmlError::MissingTitle));
     }
 }
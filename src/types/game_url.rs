@@ -0,0 +1,301 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use url::Url;
+
+/// The domain that hosts itch.io creator pages.
+pub(crate) const ITCH_IO_DOMAIN: &str = "itch.io";
+
+/// The suffix of itch.io creator subdomains.
+const ITCH_IO_SUBDOMAIN_SUFFIX: &str = ".itch.io";
+
+/// An error that may occur while parsing a game url
+#[derive(Debug, thiserror::Error)]
+pub enum ParseGameUrlError {
+    /// The string is not a valid url
+    #[error("invalid url")]
+    InvalidUrl(#[from] url::ParseError),
+
+    /// The url does not use http or https
+    #[error("unsupported url scheme `{0}`")]
+    UnsupportedScheme(String),
+
+    /// The url has no host
+    #[error("missing host")]
+    MissingHost,
+
+    /// The url points to itch.io itself, not a creator subdomain
+    #[error("missing creator subdomain")]
+    MissingCreator,
+
+    /// The creator subdomain has several labels, like `a.b.itch.io`
+    #[error("invalid creator subdomain `{0}`")]
+    InvalidCreator(String),
+
+    /// The url has no game slug
+    #[error("missing game slug")]
+    MissingSlug,
+
+    /// The url has path segments after the game slug
+    #[error("unexpected path `{0}` after game slug")]
+    UnexpectedPath(String),
+}
+
+/// A url to a game page.
+///
+/// This is normalized on construction,
+/// so trailing slashes, query strings and fragments are stripped.
+/// Game pages on itch.io look like `https://tumblewed.itch.io/doghouse-2`.
+/// Game pages on custom domains look like `https://example.com/doghouse-2` if the domain points to a creator,
+/// or `https://example.com/` if the domain points to a single game.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GameUrl {
+    /// The normalized url
+    url: Url,
+
+    /// The creator, if this is an itch.io url
+    creator: Option<String>,
+
+    /// The game slug.
+    ///
+    /// This is only `None` for custom domains that point to a single game.
+    slug: Option<String>,
+}
+
+impl GameUrl {
+    /// Parse a game url from a string.
+    pub fn parse(input: &str) -> Result<Self, ParseGameUrlError> {
+        Self::from_url(Url::parse(input)?)
+    }
+
+    /// Make a game url from a url.
+    pub fn from_url(mut url: Url) -> Result<Self, ParseGameUrlError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(ParseGameUrlError::UnsupportedScheme(url.scheme().into()));
+        }
+
+        let host = url.host_str().ok_or(ParseGameUrlError::MissingHost)?;
+        if host == ITCH_IO_DOMAIN {
+            return Err(ParseGameUrlError::MissingCreator);
+        }
+        let creator = match host.strip_suffix(ITCH_IO_SUBDOMAIN_SUFFIX) {
+            Some("www") => return Err(ParseGameUrlError::MissingCreator),
+            Some(subdomain) if !is_creator(subdomain) => {
+                return Err(ParseGameUrlError::InvalidCreator(subdomain.into()))
+            }
+            Some(subdomain) => Some(subdomain.to_string()),
+            None => None,
+        };
+
+        let mut segments = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty());
+        let slug = segments.next().map(String::from);
+        let rest: Vec<&str> = segments.collect();
+        if !rest.is_empty() {
+            return Err(ParseGameUrlError::UnexpectedPath(rest.join("/")));
+        }
+
+        if creator.is_some() {
+            if slug.is_none() {
+                return Err(ParseGameUrlError::MissingSlug);
+            }
+
+            // itch.io always redirects to https, so we skip the extra round trip.
+            url.set_scheme("https")
+                .expect("failed to set scheme to https");
+        }
+        url.set_path(slug.as_deref().unwrap_or(""));
+        url.set_query(None);
+        url.set_fragment(None);
+
+        Ok(Self { url, creator, slug })
+    }
+
+    /// Get the creator of this game.
+    ///
+    /// This is `None` if this game is hosted on a custom domain.
+    pub fn creator(&self) -> Option<&str> {
+        self.creator.as_deref()
+    }
+
+    /// Get the game slug.
+    ///
+    /// This is `None` if this game is hosted on a custom domain that points to this game alone.
+    pub fn slug(&self) -> Option<&str> {
+        self.slug.as_deref()
+    }
+
+    /// Returns true if this game is hosted on a custom domain.
+    pub fn is_custom_domain(&self) -> bool {
+        self.creator.is_none()
+    }
+
+    /// Get the canonical itch.io url for this game, given the creator.
+    ///
+    /// This is used to map custom domain urls back to the `creator.itch.io/slug` form.
+    /// This fails if this url does not contain a slug.
+    pub fn with_creator(&self, creator: &str) -> Result<Self, ParseGameUrlError> {
+        let slug = self.slug().ok_or(ParseGameUrlError::MissingSlug)?;
+        Self::parse(&format!("https://{creator}.{ITCH_IO_DOMAIN}/{slug}"))
+    }
+
+    /// Get this as a url.
+    pub fn as_url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Get this as a str.
+    pub fn as_str(&self) -> &str {
+        self.url.as_str()
+    }
+
+    /// Make a url for an endpoint under this game page.
+    ///
+    /// `path` should not start with a `/`.
+    pub(crate) fn endpoint(&self, path: &str) -> String {
+        format!("{}/{path}", self.url.as_str().trim_end_matches('/'))
+    }
+}
+
+/// Get the creator from an itch.io profile url, like `https://tumblewed.itch.io/`.
+pub(crate) fn creator_from_profile_url(url: &Url) -> Option<&str> {
+    if url.path() != "/" {
+        return None;
+    }
+
+    let creator = url.host_str()?.strip_suffix(ITCH_IO_SUBDOMAIN_SUFFIX)?;
+    is_creator(creator).then_some(creator)
+}
+
+/// Returns true if an itch.io subdomain is a single label that names a creator.
+fn is_creator(subdomain: &str) -> bool {
+    !subdomain.is_empty() && !subdomain.contains('.') && subdomain != "www"
+}
+
+impl FromStr for GameUrl {
+    type Err = ParseGameUrlError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::parse(input)
+    }
+}
+
+impl TryFrom<Url> for GameUrl {
+    type Error = ParseGameUrlError;
+
+    fn try_from(url: Url) -> Result<Self, Self::Error> {
+        Self::from_url(url)
+    }
+}
+
+impl Display for GameUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.url.fmt(f)
+    }
+}
+
+impl AsRef<str> for GameUrl {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+serde_via_str!(GameUrl, "GameUrl", {
+    "type": "string",
+    "format": "uri",
+});
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_itch_io() {
+        let inputs = [
+            "https://tumblewed.itch.io/doghouse-2",
+            "https://tumblewed.itch.io/doghouse-2/",
+            "http://tumblewed.itch.io/doghouse-2?utm_source=test#comments",
+            "https://TUMBLEWED.itch.io//doghouse-2",
+        ];
+
+        for input in inputs {
+            let url = GameUrl::parse(input).expect("failed to parse");
+            assert_eq!(url.as_str(), "https://tumblewed.itch.io/doghouse-2");
+            assert_eq!(url.creator(), Some("tumblewed"));
+            assert_eq!(url.slug(), Some("doghouse-2"));
+            assert!(!url.is_custom_domain());
+            assert_eq!(
+                url.endpoint("download_url"),
+                "https://tumblewed.itch.io/doghouse-2/download_url"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_custom_domain() {
+        let url =
+            GameUrl::parse("http://games.example.com/doghouse-2/?a=b").expect("failed to parse");
+        assert_eq!(url.as_str(), "http://games.example.com/doghouse-2");
+        assert_eq!(url.creator(), None);
+        assert_eq!(url.slug(), Some("doghouse-2"));
+        assert!(url.is_custom_domain());
+
+        let canonical = url
+            .with_creator("tumblewed")
+            .expect("failed to canonicalize");
+        assert_eq!(canonical.as_str(), "https://tumblewed.itch.io/doghouse-2");
+
+        let url = GameUrl::parse("https://doghouse.example.com/").expect("failed to parse");
+        assert_eq!(url.slug(), None);
+        assert_eq!(
+            url.endpoint("download_url"),
+            "https://doghouse.example.com/download_url"
+        );
+        assert!(matches!(
+            url.with_creator("tumblewed"),
+            Err(ParseGameUrlError::MissingSlug)
+        ));
+
+        let url = GameUrl::parse("https://notitch.io/doghouse-2").expect("failed to parse");
+        assert!(url.is_custom_domain());
+        assert_eq!(url.slug(), Some("doghouse-2"));
+        assert_eq!(
+            creator_from_profile_url(&Url::parse("https://notitch.io/").expect("invalid url")),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(matches!(
+            GameUrl::parse("not a url"),
+            Err(ParseGameUrlError::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            GameUrl::parse("ftp://tumblewed.itch.io/doghouse-2"),
+            Err(ParseGameUrlError::UnsupportedScheme(_))
+        ));
+        assert!(matches!(
+            GameUrl::parse("https://itch.io/games"),
+            Err(ParseGameUrlError::MissingCreator)
+        ));
+        assert!(matches!(
+            GameUrl::parse("https://a.b.itch.io/doghouse-2"),
+            Err(ParseGameUrlError::InvalidCreator(creator)) if creator == "a.b"
+        ));
+        assert_eq!(
+            creator_from_profile_url(&Url::parse("https://a.b.itch.io/").expect("invalid url")),
+            None
+        );
+        assert!(matches!(
+            GameUrl::parse("https://tumblewed.itch.io/"),
+            Err(ParseGameUrlError::MissingSlug)
+        ));
+        assert!(matches!(
+            GameUrl::parse("https://tumblewed.itch.io/doghouse-2/purchase"),
+            Err(ParseGameUrlError::UnexpectedPath(_))
+        ));
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The id of a game
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct GameId(pub u64);
+
+impl FromStr for GameId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        input.parse().map(Self)
+    }
+}
+
+impl Display for GameId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<u64> for GameId {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<GameId> for u64 {
+    fn from(id: GameId) -> Self {
+        id.0
+    }
+}
+
+/// The id of an upload
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct UploadId(pub u64);
+
+impl FromStr for UploadId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        input.parse().map(Self)
+    }
+}
+
+impl Display for UploadId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<u64> for UploadId {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<UploadId> for u64 {
+    fn from(id: UploadId) -> Self {
+        id.0
+    }
+}
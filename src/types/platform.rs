@@ -0,0 +1,124 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A platform
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Platform {
+    /// Windows
+    Windows,
+
+    /// Linux
+    Linux,
+
+    /// MacOs
+    MacOs,
+
+    /// Android
+    Android,
+
+    /// Web, or HTML5
+    Web,
+
+    /// A platform we do not know about.
+    ///
+    /// This contains the icon class, without the `icon-` prefix.
+    /// Make this with [`Platform::other`], so it never holds the name of a known platform.
+    Other(String),
+}
+
+impl Platform {
+    /// Get a platform from an itch.io icon class, without the `icon-` prefix.
+    pub fn from_icon_class(class: &str) -> Self {
+        match class {
+            "windows8" => Self::Windows,
+            "tux" => Self::Linux,
+            "apple" => Self::MacOs,
+            "android" => Self::Android,
+            "html5" => Self::Web,
+            _ => Self::other(class),
+        }
+    }
+
+    /// Get a platform from a name that is not known to itch.io.
+    ///
+    /// Names returned by [`Platform::as_str`] give their platform instead of [`Platform::Other`],
+    /// so that platforms round-trip through [`Display`] and serde.
+    pub fn other(name: &str) -> Self {
+        match name {
+            "Windows" => Self::Windows,
+            "Linux" => Self::Linux,
+            "MacOs" => Self::MacOs,
+            "Android" => Self::Android,
+            "Web" => Self::Web,
+            _ => Self::Other(name.into()),
+        }
+    }
+
+    /// Get the platform of the host.
+    ///
+    /// Returns `None` if the host platform is not one itch.io knows about.
+    pub fn current() -> Option<Self> {
+        if cfg!(target_os = "windows") {
+            Some(Self::Windows)
+        } else if cfg!(target_os = "android") {
+            Some(Self::Android)
+        } else if cfg!(target_os = "linux") {
+            Some(Self::Linux)
+        } else if cfg!(target_os = "macos") {
+            Some(Self::MacOs)
+        } else if cfg!(target_family = "wasm") {
+            Some(Self::Web)
+        } else {
+            None
+        }
+    }
+
+    /// Get this as a str.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Windows => "Windows",
+            Self::Linux => "Linux",
+            Self::MacOs => "MacOs",
+            Self::Android => "Android",
+            Self::Web => "Web",
+            Self::Other(other) => other,
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = std::convert::Infallible;
+
+    /// Parse a platform from the output of [`Platform::as_str`].
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Ok(Self::other(input))
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+serde_via_str!(Platform, "Platform", {
+    "type": "string",
+    "description": "`Windows`, `Linux`, `MacOs`, `Android`, `Web`, or an unknown itch.io icon class",
+});
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn other() {
+        assert_eq!(Platform::other("Windows"), Platform::Windows);
+        assert_eq!(Platform::from_icon_class("Web"), Platform::Web);
+        assert_eq!(Platform::other("psp"), Platform::Other("psp".into()));
+
+        for platform in [Platform::MacOs, Platform::other("psp")] {
+            let parsed: Platform = platform.to_string().parse().expect("failed to parse");
+            assert_eq!(parsed, platform);
+        }
+    }
+}
@@ -1,6 +1,15 @@
 /// The purchase dialog
 #[derive(Debug, serde::Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct PurchaseDialog {
     /// Dialog content
     pub content: String,
 }
+
+impl PurchaseDialog {
+    /// Parse this from a json response body.
+    pub fn from_json(body: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(body)
+    }
+}
@@ -0,0 +1,337 @@
+use once_cell::sync::Lazy;
+use scraper::Selector;
+use std::fmt::Display;
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The default selectors
+pub(crate) static DEFAULT_SELECTORS: Lazy<Selectors> = Lazy::new(Selectors::default);
+
+/// An error that may occur while parsing a css selector
+#[derive(Debug, thiserror::Error)]
+#[error("invalid selector `{selector}`: {message}")]
+pub struct ParseCssSelectorError {
+    /// The selector
+    pub selector: String,
+
+    /// The error message
+    pub message: String,
+}
+
+/// An error that may occur while loading selectors
+#[derive(Debug, thiserror::Error)]
+pub enum LoadSelectorsError {
+    /// Failed to read the file
+    #[error("failed to read selectors file")]
+    Io(#[from] std::io::Error),
+
+    /// Invalid json
+    #[error("invalid json")]
+    Json(#[from] serde_json::Error),
+
+    /// Invalid toml
+    #[cfg(feature = "toml")]
+    #[error("invalid toml")]
+    Toml(#[from] toml::de::Error),
+
+    /// The file extension is not supported
+    #[error("unsupported selectors file extension `{0}`")]
+    UnsupportedExtension(String),
+}
+
+/// A css selector.
+///
+/// This keeps the source string around, for serialization and diagnostics.
+#[derive(Debug, Clone)]
+pub struct CssSelector {
+    /// The source string
+    source: String,
+
+    /// The parsed selector
+    selector: Selector,
+}
+
+impl CssSelector {
+    /// Parse a css selector.
+    pub fn parse(source: &str) -> Result<Self, ParseCssSelectorError> {
+        let selector = Selector::parse(source).map_err(|error| ParseCssSelectorError {
+            selector: source.into(),
+            message: error.to_string(),
+        })?;
+
+        Ok(Self {
+            source: source.into(),
+            selector,
+        })
+    }
+
+    /// Get the source string.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Parse a default selector.
+    ///
+    /// # Panics
+    /// Panics if the selector is invalid.
+    fn new_default(source: &str) -> Self {
+        Self::parse(source).expect("invalid default selector")
+    }
+}
+
+impl Deref for CssSelector {
+    type Target = Selector;
+
+    fn deref(&self) -> &Self::Target {
+        &self.selector
+    }
+}
+
+impl FromStr for CssSelector {
+    type Err = ParseCssSelectorError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::parse(input)
+    }
+}
+
+impl Display for CssSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl serde::Serialize for CssSelector {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for CssSelector {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let source = String::deserialize(deserializer)?;
+        Self::parse(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The css selectors used to parse pages.
+///
+/// The defaults track the current itch.io markup.
+/// These can be overridden at runtime, like when itch.io changes its markup before a crate release.
+/// When loading from a file, missing selectors keep their default values,
+/// so a file only needs to contain the selectors that should change:
+///
+/// ```toml
+/// [game_page]
+/// title = ".game_title_v2"
+/// ```
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Selectors {
+    /// Game page selectors
+    pub game_page: GamePageSelectors,
+
+    /// Download page selectors
+    pub download_page: DownloadPageSelectors,
+
+    /// Upload selectors, relative to an upload element
+    pub upload: UploadSelectors,
+
+    /// Download lightbox selectors
+    pub lightbox: LightboxSelectors,
+}
+
+impl Selectors {
+    /// Load selectors from a json string.
+    pub fn from_json_str(input: &str) -> Result<Self, LoadSelectorsError> {
+        Ok(serde_json::from_str(input)?)
+    }
+
+    /// Load selectors from a toml string.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(input: &str) -> Result<Self, LoadSelectorsError> {
+        Ok(toml::from_str(input)?)
+    }
+
+    /// Load selectors from a file.
+    ///
+    /// The format is picked from the file extension.
+    pub fn from_path<P>(path: P) -> Result<Self, LoadSelectorsError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("");
+        match extension {
+            "json" => Self::from_json_str(&std::fs::read_to_string(path)?),
+            #[cfg(feature = "toml")]
+            "toml" => Self::from_toml_str(&std::fs::read_to_string(path)?),
+            _ => Err(LoadSelectorsError::UnsupportedExtension(extension.into())),
+        }
+    }
+}
+
+/// Game page selectors
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamePageSelectors {
+    /// The game title
+    pub title: CssSelector,
+
+    /// The meta tag with the page url
+    pub twitter_url: CssSelector,
+
+    /// The meta tag with the game path, which contains the game id
+    pub game_path: CssSelector,
+
+    /// The oembed link, used to find the itch.io url of games on custom domains
+    pub oembed: CssSelector,
+
+    /// Author profile links, used to find the itch.io url of games on custom domains
+    pub author_link: CssSelector,
+
+    /// The meta tag with the csrf token
+    pub csrf_token: CssSelector,
+
+    /// Upload elements
+    pub upload: CssSelector,
+
+    /// The html game placeholder
+    pub view_html: CssSelector,
+
+    /// The iframe inside the html game placeholder data
+    pub iframe: CssSelector,
+}
+
+impl Default for GamePageSelectors {
+    fn default() -> Self {
+        Self {
+            title: CssSelector::new_default(".game_title"),
+            twitter_url: CssSelector::new_default("meta[name=\"twitter:url\"]"),
+            game_path: CssSelector::new_default("meta[name=\"itch:path\"]"),
+            oembed: CssSelector::new_default("link[type=\"application/json+oembed\"]"),
+            author_link: CssSelector::new_default(".game_info_panel_widget a[href]"),
+            csrf_token: CssSelector::new_default("meta[name=\"csrf_token\"]"),
+            upload: CssSelector::new_default(".upload"),
+            view_html: CssSelector::new_default(".view_html_game_page .iframe_placeholder"),
+            iframe: CssSelector::new_default("iframe"),
+        }
+    }
+}
+
+/// Download page selectors
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DownloadPageSelectors {
+    /// Upload elements
+    pub upload: CssSelector,
+}
+
+impl Default for DownloadPageSelectors {
+    fn default() -> Self {
+        Self {
+            upload: CssSelector::new_default(".upload"),
+        }
+    }
+}
+
+/// Download lightbox selectors
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightboxSelectors {
+    /// The thank-you text
+    pub thank_you: CssSelector,
+
+    /// Notes from the creator
+    pub creator_note: CssSelector,
+
+    /// Error messages
+    pub error: CssSelector,
+}
+
+impl Default for LightboxSelectors {
+    fn default() -> Self {
+        Self {
+            thank_you: CssSelector::new_default("h2"),
+            creator_note: CssSelector::new_default(".user_formatted"),
+            error: CssSelector::new_default(".form_errors li, .error_message"),
+        }
+    }
+}
+
+/// Upload selectors, relative to an upload element
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadSelectors {
+    /// The upload title
+    pub title: CssSelector,
+
+    /// The file size
+    pub file_size: CssSelector,
+
+    /// The download button, which contains the upload id
+    pub download_btn: CssSelector,
+
+    /// The platforms container
+    pub platforms: CssSelector,
+
+    /// Platform icons, inside the platforms container
+    pub icon: CssSelector,
+
+    /// The upload date
+    pub upload_date: CssSelector,
+
+    /// The version or build name
+    pub version: CssSelector,
+
+    /// Tags, like `Demo`
+    pub tag: CssSelector,
+}
+
+impl Default for UploadSelectors {
+    fn default() -> Self {
+        Self {
+            title: CssSelector::new_default(".name"),
+            file_size: CssSelector::new_default(".file_size > span"),
+            download_btn: CssSelector::new_default(".download_btn"),
+            platforms: CssSelector::new_default(".download_platforms"),
+            icon: CssSelector::new_default("span.icon"),
+            upload_date: CssSelector::new_default(".upload_date abbr"),
+            version: CssSelector::new_default(".version_name"),
+            tag: CssSelector::new_default(".upload_tag, .download_tag"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn partial_override() {
+        let selectors = Selectors::from_json_str(r#"{"game_page": {"title": ".game_title_v2"}}"#)
+            .expect("failed to load selectors");
+        assert_eq!(selectors.game_page.title.as_str(), ".game_title_v2");
+        assert_eq!(
+            selectors.game_page.csrf_token.as_str(),
+            "meta[name=\"csrf_token\"]"
+        );
+        assert_eq!(selectors.upload.file_size.as_str(), ".file_size > span");
+    }
+
+    #[test]
+    fn invalid_selector() {
+        assert!(Selectors::from_json_str(r#"{"game_page": {"title": "..."}}"#).is_err());
+        assert!(Selectors::from_json_str(r#"{"game_page": {"titel": ".title"}}"#).is_err());
+    }
+}
@@ -0,0 +1,455 @@
+use crate::types::selectors::CssSelector;
+use crate::types::selectors::UploadSelectors;
+use crate::types::ByteSize;
+use crate::types::Diagnostic;
+use crate::types::ParseByteSizeError;
+use crate::types::Platform;
+use crate::types::UploadId;
+use scraper::ElementRef;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Error that may occur while parsing an upload
+#[derive(Debug, thiserror::Error)]
+pub enum FromElementError {
+    /// Missing Title
+    #[error("missing title")]
+    MissingTitle,
+
+    /// Missing file size, on an upload that is not external
+    #[error("missing file size")]
+    MissingFileSize,
+
+    /// Invalid file size
+    #[error("invalid file size")]
+    InvalidFileSize(#[source] ParseByteSizeError),
+
+    /// Missing id
+    #[error("missing id")]
+    MissingId,
+
+    /// Invalid id
+    #[error("invalid id")]
+    InvalidId(#[source] std::num::ParseIntError),
+
+    /// Missing platforms
+    #[error("missing platforms")]
+    MissingPlatforms,
+
+    /// Missing a platform string
+    #[error("missing platform string")]
+    MissingPlatformString,
+}
+
+impl FromElementError {
+    /// Get the name of the upload field that failed to parse.
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::MissingTitle => "title",
+            Self::MissingFileSize | Self::InvalidFileSize(_) => "size",
+            Self::MissingId | Self::InvalidId(_) => "id",
+            Self::MissingPlatforms | Self::MissingPlatformString => "platforms",
+        }
+    }
+
+    /// Get the selector used to locate the upload field that failed to parse.
+    pub fn selector<'a>(&self, selectors: &'a UploadSelectors) -> &'a CssSelector {
+        match self {
+            Self::MissingTitle => &selectors.title,
+            Self::MissingFileSize | Self::InvalidFileSize(_) => &selectors.file_size,
+            Self::MissingId | Self::InvalidId(_) => &selectors.download_btn,
+            Self::MissingPlatforms => &selectors.platforms,
+            Self::MissingPlatformString => &selectors.icon,
+        }
+    }
+}
+
+/// An upload.
+///
+/// This is a file attached to a game,
+/// as listed on either the game page or the download page.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct Upload {
+    /// Upload id, if it exists.
+    ///
+    /// This is always present on the download page,
+    /// but may be missing from the game page if the game is not free.
+    pub id: Option<UploadId>,
+
+    /// Upload title
+    pub title: String,
+
+    /// Upload size.
+    ///
+    /// This is `None` for external uploads, which itch.io shows without a size.
+    pub size: Option<ByteSize>,
+
+    /// The platforms this upload is for
+    pub platforms: Vec<Platform>,
+
+    /// The upload date, as displayed by itch.io.
+    ///
+    /// This looks like `21 March 2021 @ 12:34 UTC`.
+    pub upload_date: Option<String>,
+
+    /// The version or build name of this upload, if it exists
+    pub version: Option<String>,
+
+    /// Whether this upload is a demo
+    pub demo: bool,
+
+    /// Whether this upload is a soundtrack
+    pub soundtrack: bool,
+
+    /// Whether this upload is a preorder
+    pub preorder: bool,
+
+    /// Whether this upload is hosted externally
+    pub external: bool,
+}
+
+impl Upload {
+    /// Parse an upload from an element
+    pub(crate) fn from_element(
+        element: ElementRef,
+        selectors: &UploadSelectors,
+    ) -> Result<Self, FromElementError> {
+        let title = element
+            .select(&selectors.title)
+            .next()
+            .and_then(|element| element.text().next())
+            .ok_or(FromElementError::MissingTitle)?
+            .to_string();
+
+        let download_btn = element.select(&selectors.download_btn).next();
+        let external = element
+            .value()
+            .classes()
+            .any(|class| class == "external_upload")
+            || download_btn
+                .is_some_and(|element| element.value().classes().any(|class| class == "external"));
+
+        let size = element
+            .select(&selectors.file_size)
+            .next()
+            .and_then(|element| element.text().next())
+            .map(|size| size.parse().map_err(FromElementError::InvalidFileSize))
+            .transpose()?;
+        if size.is_none() && !external {
+            return Err(FromElementError::MissingFileSize);
+        }
+
+        let id = download_btn
+            .and_then(|element| element.value().attr("data-upload_id"))
+            .map(|id_str| id_str.parse().map_err(FromElementError::InvalidId))
+            .transpose()?;
+
+        let platforms = element
+            .select(&selectors.platforms)
+            .next()
+            .ok_or(FromElementError::MissingPlatforms)?
+            .select(&selectors.icon)
+            .map(|icon_el| {
+                icon_el
+                    .value()
+                    .classes()
+                    .find_map(|class| class.strip_prefix("icon-"))
+                    .map(Platform::from_icon_class)
+                    .ok_or(FromElementError::MissingPlatformString)
+            })
+            .collect::<Result<_, _>>()?;
+
+        let upload_date = element
+            .select(&selectors.upload_date)
+            .next()
+            .and_then(|element| element.value().attr("title"))
+            .map(String::from);
+
+        let version = element
+            .select(&selectors.version)
+            .next()
+            .map(|element| element.text().collect::<String>().trim().to_string())
+            .filter(|version| !version.is_empty());
+
+        let mut demo = false;
+        let mut soundtrack = false;
+        let mut preorder = false;
+        for tag in element.select(&selectors.tag) {
+            let tag = tag.text().collect::<String>().trim().to_ascii_lowercase();
+            match tag.as_str() {
+                "demo" => demo = true,
+                "soundtrack" => soundtrack = true,
+                "preorder" | "pre-order" => preorder = true,
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            id,
+            title,
+            size,
+            platforms,
+            upload_date,
+            version,
+            demo,
+            soundtrack,
+            preorder,
+            external,
+        })
+    }
+
+    /// Returns true if this upload is for the given platform.
+    pub fn supports(&self, platform: &Platform) -> bool {
+        self.platforms.contains(platform)
+    }
+
+    /// Returns true if this upload has the given tag.
+    pub fn has_tag(&self, tag: UploadTag) -> bool {
+        match tag {
+            UploadTag::Demo => self.demo,
+            UploadTag::Soundtrack => self.soundtrack,
+            UploadTag::Preorder => self.preorder,
+            UploadTag::External => self.external,
+        }
+    }
+}
+
+/// An error that may occur while parsing an upload tag
+#[derive(Debug, thiserror::Error)]
+#[error("unknown upload tag `{0}`")]
+pub struct ParseUploadTagError(pub String);
+
+/// A tag on an upload
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub enum UploadTag {
+    /// A demo
+    Demo,
+
+    /// A soundtrack
+    Soundtrack,
+
+    /// A preorder
+    Preorder,
+
+    /// Hosted externally
+    External,
+}
+
+impl UploadTag {
+    /// All upload tags
+    pub const ALL: &'static [Self] =
+        &[Self::Demo, Self::Soundtrack, Self::Preorder, Self::External];
+
+    /// Get this as a str.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Demo => "demo",
+            Self::Soundtrack => "soundtrack",
+            Self::Preorder => "preorder",
+            Self::External => "external",
+        }
+    }
+}
+
+impl FromStr for UploadTag {
+    type Err = ParseUploadTagError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|tag| tag.as_str() == input)
+            .ok_or_else(|| ParseUploadTagError(input.into()))
+    }
+}
+
+impl Display for UploadTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Parse upload rows, skipping rows that fail to parse.
+///
+/// A warning is recorded for each skipped row.
+/// If `require_id` is true, rows without an upload id are skipped.
+pub(crate) fn parse_uploads<'a, E>(
+    elements: impl Iterator<Item = ElementRef<'a>>,
+    selectors: &UploadSelectors,
+    require_id: bool,
+    warnings: &mut Vec<Diagnostic<E>>,
+) -> Vec<Upload>
+where
+    E: From<FromElementError>,
+{
+    let mut uploads = Vec::new();
+    for (i, element) in elements.enumerate() {
+        let result = Upload::from_element(element, selectors).and_then(|upload| {
+            if require_id && upload.id.is_none() {
+                return Err(FromElementError::MissingId);
+            }
+            Ok(upload)
+        });
+
+        match result {
+            Ok(upload) => uploads.push(upload),
+            Err(error) => {
+                let field = format!("uploads[{i}].{}", error.field());
+                let selector = error.selector(selectors);
+                warnings.push(
+                    Diagnostic::new(field, E::from(error))
+                        .with_selector(selector)
+                        .with_snippet(element),
+                );
+            }
+        }
+    }
+
+    uploads
+}
+
+/// Pick the best upload for a platform.
+///
+/// This prefers full releases over demos, soundtracks, preorders and external uploads,
+/// and uploads made for the given platform alone over uploads that bundle multiple platforms.
+/// Ties are broken by page order.
+pub(crate) fn best_upload_for<'a>(
+    uploads: &'a [Upload],
+    platform: &Platform,
+) -> Option<&'a Upload> {
+    uploads
+        .iter()
+        .filter(|upload| upload.supports(platform))
+        .min_by_key(|upload| {
+            (
+                upload.demo,
+                upload.soundtrack,
+                upload.preorder,
+                upload.external,
+                upload.platforms.len(),
+            )
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use scraper::Html;
+
+    #[test]
+    fn from_element() {
+        let html = Html::parse_fragment(
+            r#"<div class="upload">
+    <a class="button download_btn" data-upload_id="1234">Download</a>
+    <div class="info_column">
+        <div class="upload_name">
+            <strong class="name" title="doghouse-demo.zip">doghouse-demo.zip</strong>
+            <span class="upload_tag">Demo</span>
+            <span class="file_size"><span>5 MB</span></span>
+            <span class="download_platforms">
+                <span class="icon icon-windows8" title="Download for Windows"></span>
+                <span class="icon icon-tux" title="Download for Linux"></span>
+                <span class="icon icon-psp" title="Download for PSP"></span>
+            </span>
+        </div>
+        <div class="upload_date">
+            <span class="version_name">Version 3</span>
+            <abbr title="21 March 2021 @ 12:34 UTC">Mar 21, 2021</abbr>
+        </div>
+    </div>
+</div>"#,
+        );
+        let selectors = UploadSelectors::default();
+        let selector = CssSelector::parse(".upload").expect("invalid selector");
+        let element = html.select(&selector).next().expect("missing upload");
+        let upload = Upload::from_element(element, &selectors).expect("failed to parse upload");
+
+        assert_eq!(upload.id, Some(UploadId(1234)));
+        assert_eq!(upload.title, "doghouse-demo.zip");
+        assert_eq!(upload.size.map(|size| size.bytes()), Some(5_000_000));
+        assert_eq!(
+            upload.platforms,
+            [
+                Platform::Windows,
+                Platform::Linux,
+                Platform::Other("psp".into())
+            ]
+        );
+        assert_eq!(
+            upload.upload_date.as_deref(),
+            Some("21 March 2021 @ 12:34 UTC")
+        );
+        assert_eq!(upload.version.as_deref(), Some("Version 3"));
+        assert!(upload.demo);
+        assert!(!upload.soundtrack);
+        assert!(!upload.preorder);
+        assert!(!upload.external);
+
+        assert!(upload.supports(&Platform::Linux));
+        assert!(!upload.supports(&Platform::Android));
+    }
+
+    #[test]
+    fn external_without_size() {
+        let html = Html::parse_fragment(
+            r#"<div class="upload external_upload">
+    <strong class="name">On Dropbox</strong>
+    <span class="download_platforms"></span>
+</div>
+<div class="upload">
+    <strong class="name">doghouse.zip</strong>
+    <span class="download_platforms"></span>
+</div>"#,
+        );
+        let selectors = UploadSelectors::default();
+        let selector = CssSelector::parse(".upload").expect("invalid selector");
+        let mut elements = html.select(&selector);
+
+        let external = elements.next().expect("missing upload");
+        let upload = Upload::from_element(external, &selectors).expect("failed to parse upload");
+        assert!(upload.external);
+        assert_eq!(upload.size, None);
+
+        let missing = elements.next().expect("missing upload");
+        assert!(matches!(
+            Upload::from_element(missing, &selectors),
+            Err(FromElementError::MissingFileSize)
+        ));
+    }
+
+    #[test]
+    fn best_upload() {
+        let upload = |title: &str, platforms: &[Platform], demo: bool| Upload {
+            id: None,
+            title: title.into(),
+            size: Some(ByteSize::from_bytes(1)),
+            platforms: platforms.to_vec(),
+            upload_date: None,
+            version: None,
+            demo,
+            soundtrack: false,
+            preorder: false,
+            external: false,
+        };
+        let uploads = [
+            upload("demo.zip", &[Platform::Windows], true),
+            upload("bundle.zip", &[Platform::Windows, Platform::Linux], false),
+            upload("windows.zip", &[Platform::Windows], false),
+            upload("windows-2.zip", &[Platform::Windows], false),
+        ];
+
+        let best = |platform| best_upload_for(&uploads, &platform).map(|upload| &*upload.title);
+        assert_eq!(best(Platform::Windows), Some("windows.zip"));
+        assert_eq!(best(Platform::Linux), Some("bundle.zip"));
+        assert_eq!(best(Platform::Android), None);
+    }
+}